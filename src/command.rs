@@ -0,0 +1,1107 @@
+use crate::model::{CaretPosition, Document, Line, LineKey, char_to_byte_index};
+use std::ops::Range;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MoveDirection {
+    Up,
+    Down,
+}
+
+impl MoveDirection {
+    pub fn reversed(self) -> Self {
+        match self {
+            MoveDirection::Up => MoveDirection::Down,
+            MoveDirection::Down => MoveDirection::Up,
+        }
+    }
+}
+
+// Where the caret should go after a command has been applied.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CaretTarget {
+    Keep,
+    Line(usize),
+    Position(CaretPosition),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum EditCommand {
+    InsertText {
+        line: usize,
+        column: usize,
+        text: String,
+    },
+    DeleteText {
+        line: usize,
+        column: usize,
+        len: usize,
+    },
+    SetText {
+        line: usize,
+        text: String,
+    },
+    SplitLine {
+        line: usize,
+        column: usize,
+    },
+    DeleteRange {
+        start: CaretPosition,
+        end: CaretPosition,
+    },
+    InsertFragment {
+        line: usize,
+        column: usize,
+        fragment: Vec<(u32, String)>,
+    },
+    MergeWithPrevious {
+        line: usize,
+    },
+    MergeWithNext {
+        line: usize,
+    },
+    DeleteSubtree {
+        line: usize,
+    },
+    Indent {
+        lines: Range<usize>,
+    },
+    Dedent {
+        lines: Range<usize>,
+    },
+    MoveLine {
+        line: usize,
+        direction: MoveDirection,
+    },
+    MoveSubtree {
+        line: usize,
+        direction: MoveDirection,
+    },
+    DuplicateSubtree {
+        line: usize,
+        direction: MoveDirection,
+    },
+    InsertRootLine {
+        line: usize,
+    },
+    // Inserts whole lines before index `at`, each indented by `indent` plus
+    // its relative level in the fragment.
+    InsertLines {
+        at: usize,
+        indent: u32,
+        fragment: Vec<(u32, String)>,
+    },
+    ToggleCheckbox {
+        line: usize,
+    },
+    ToggleFold {
+        line: usize,
+    },
+    FoldToLevel {
+        level: u32,
+    },
+    ToggleFoldAll,
+    FoldSubtree {
+        line: usize,
+    },
+    SetFolds {
+        folds: Vec<(usize, bool)>,
+    },
+    CompressIndentJumps,
+    ReplaceLines {
+        start: usize,
+        removed: Vec<Line>,
+        inserted: Vec<Line>,
+    },
+}
+
+impl EditCommand {
+    // Applies the command and reports where the caret should go, or `None`
+    // when the command did not change the document.
+    pub fn apply(&self, document: &mut Document) -> Option<CaretTarget> {
+        let target = self.apply_lines(document)?;
+        if self.changes_structure() {
+            document.touch_structure();
+        }
+        Some(target)
+    }
+
+    fn changes_structure(&self) -> bool {
+        !matches!(
+            self,
+            EditCommand::InsertText { .. }
+                | EditCommand::DeleteText { .. }
+                | EditCommand::SetText { .. }
+                | EditCommand::ToggleCheckbox { .. }
+        )
+    }
+
+    fn apply_lines(&self, document: &mut Document) -> Option<CaretTarget> {
+        match self {
+            EditCommand::InsertText { line, column, text } => {
+                if text.is_empty() {
+                    return None;
+                }
+                let target = document.lines.get_mut(*line)?;
+                let at = char_to_byte_index(&target.text, *column);
+                target.text.insert_str(at, text);
+                let column = target.text[..at].chars().count() + text.chars().count();
+                Some(CaretTarget::Position(CaretPosition {
+                    line: *line,
+                    column,
+                }))
+            }
+            EditCommand::DeleteText { line, column, len } => {
+                let target = document.lines.get_mut(*line)?;
+                let start = char_to_byte_index(&target.text, *column);
+                let end = char_to_byte_index(&target.text, column + len);
+                if start >= end {
+                    return None;
+                }
+                target.text.replace_range(start..end, "");
+                let column = target.text[..start].chars().count();
+                Some(CaretTarget::Position(CaretPosition {
+                    line: *line,
+                    column,
+                }))
+            }
+            EditCommand::SetText { line, text } => {
+                let target = document.lines.get_mut(*line)?;
+                if target.text == *text {
+                    return None;
+                }
+                target.text = text.clone();
+                Some(CaretTarget::Keep)
+            }
+            // Splitting a folded line keeps its hidden block attached to the
+            // head: the new sibling goes after the block, or above the head
+            // when the caret is at its start.
+            EditCommand::SplitLine { line, column } => {
+                if *line >= document.lines.len() {
+                    return None;
+                }
+                let new_id = document.next_line_id();
+                let insert_at = split_destination(document, *line, *column);
+                let current = &mut document.lines[*line];
+                let trailing = if insert_at == *line {
+                    String::new()
+                } else {
+                    let split_at = char_to_byte_index(&current.text, *column);
+                    current.text.split_off(split_at)
+                };
+                let new_line = Line {
+                    id: new_id,
+                    indent: current.indent,
+                    text: trailing,
+                    collapsed: false,
+                    source_indent: None,
+                };
+                document.lines.insert(insert_at, new_line);
+                let caret_line = if insert_at == *line {
+                    line + 1
+                } else {
+                    insert_at
+                };
+                Some(CaretTarget::Position(CaretPosition {
+                    line: caret_line,
+                    column: 0,
+                }))
+            }
+            EditCommand::DeleteRange { start, end } => {
+                if start >= end || end.line >= document.lines.len() {
+                    return None;
+                }
+                if start.line == end.line {
+                    return EditCommand::DeleteText {
+                        line: start.line,
+                        column: start.column,
+                        len: end.column - start.column,
+                    }
+                    .apply_lines(document);
+                }
+
+                let first = &mut document.lines[start.line];
+                let cut_at = char_to_byte_index(&first.text, start.column);
+                first.text.truncate(cut_at);
+                let last = &mut document.lines[end.line];
+                let keep_from = char_to_byte_index(&last.text, end.column);
+                last.text.replace_range(..keep_from, "");
+                document.lines.drain(start.line + 1..end.line);
+                merge_into_previous(document, start.line + 1);
+                Some(CaretTarget::Position(*start))
+            }
+            // The first fragment line joins the text before the caret, the rest
+            // become new lines indented relative to the caret line, and the
+            // text after the caret moves to the end of the last one.
+            EditCommand::InsertFragment {
+                line,
+                column,
+                fragment,
+            } => {
+                let (first, rest) = fragment.split_first()?;
+                if rest.is_empty() {
+                    return EditCommand::InsertText {
+                        line: *line,
+                        column: *column,
+                        text: first.1.clone(),
+                    }
+                    .apply_lines(document);
+                }
+
+                let current = document.lines.get_mut(*line)?;
+                let split_at = char_to_byte_index(&current.text, *column);
+                let tail = current.text.split_off(split_at);
+                current.text.push_str(&first.1);
+                let base = current.indent;
+
+                let mut new_lines: Vec<Line> = rest
+                    .iter()
+                    .map(|(indent, text)| Line {
+                        id: document.next_line_id(),
+                        indent: base + indent,
+                        text: text.clone(),
+                        collapsed: false,
+                        source_indent: None,
+                    })
+                    .collect();
+                let last = new_lines.last_mut().expect("fragment has several lines");
+                let caret_column = last.text.chars().count();
+                last.text.push_str(&tail);
+
+                let count = new_lines.len();
+                document.lines.splice(line + 1..line + 1, new_lines);
+                Some(CaretTarget::Position(CaretPosition {
+                    line: line + count,
+                    column: caret_column,
+                }))
+            }
+            EditCommand::MergeWithPrevious { line } => {
+                if *line == 0 || *line >= document.lines.len() {
+                    return None;
+                }
+                let column = merge_into_previous(document, *line);
+                Some(CaretTarget::Position(CaretPosition {
+                    line: line - 1,
+                    column,
+                }))
+            }
+            EditCommand::DeleteSubtree { line } => {
+                if *line >= document.lines.len() {
+                    return None;
+                }
+                let range = document.subtree_range(LineKey::Index(*line));
+                if range.len() == document.lines.len() {
+                    let empty = Line {
+                        id: document.next_line_id(),
+                        indent: 0,
+                        text: String::new(),
+                        collapsed: false,
+                        source_indent: None,
+                    };
+                    document.lines.splice(range, [empty]);
+                    return Some(CaretTarget::Line(0));
+                }
+                document.lines.drain(range);
+                let caret_line = (*line).min(document.lines.len() - 1);
+                Some(CaretTarget::Line(caret_line))
+            }
+            EditCommand::MergeWithNext { line } => {
+                if line + 1 >= document.lines.len() {
+                    return None;
+                }
+                let column = merge_into_previous(document, line + 1);
+                Some(CaretTarget::Position(CaretPosition {
+                    line: *line,
+                    column,
+                }))
+            }
+            EditCommand::Indent { lines } => {
+                let mut changed = false;
+                for index in lines.clone() {
+                    if let Some(line) = document.lines.get_mut(index) {
+                        line.indent = line.indent.saturating_add(1);
+                        changed = true;
+                    }
+                }
+                changed.then_some(CaretTarget::Keep)
+            }
+            EditCommand::Dedent { lines } => {
+                let needs_change = lines.clone().any(|index| {
+                    document
+                        .lines
+                        .get(index)
+                        .is_some_and(|line| line.indent > 0)
+                });
+                if !needs_change {
+                    return None;
+                }
+                for index in lines.clone() {
+                    if let Some(line) = document.lines.get_mut(index) {
+                        line.indent = line.indent.saturating_sub(1);
+                    }
+                }
+                Some(CaretTarget::Keep)
+            }
+            EditCommand::MoveLine { line, direction } => {
+                let plan = plan_line_move(document, *line, *direction)?;
+                Some(plan.apply(document))
+            }
+            EditCommand::MoveSubtree { line, direction } => {
+                let plan = plan_subtree_move(document, *line, *direction)?;
+                Some(plan.apply(document))
+            }
+            EditCommand::DuplicateSubtree { line, direction } => {
+                if *line >= document.lines.len() {
+                    return None;
+                }
+                let range = document.subtree_range(LineKey::Index(*line));
+                let new_block: Vec<Line> = document
+                    .lines
+                    .range_to_vec(range.clone())
+                    .into_iter()
+                    .map(|mut new_line| {
+                        new_line.id = document.next_line_id();
+                        new_line
+                    })
+                    .collect();
+                let insert_at = match direction {
+                    MoveDirection::Up => range.start,
+                    MoveDirection::Down => range.end,
+                };
+                document.lines.splice(insert_at..insert_at, new_block);
+                Some(CaretTarget::Line(insert_at))
+            }
+            EditCommand::InsertRootLine { line } => {
+                let insert_at = (line + 1).min(document.lines.len());
+                let new_line = Line {
+                    id: document.next_line_id(),
+                    indent: 0,
+                    text: String::new(),
+                    collapsed: false,
+                    source_indent: None,
+                };
+                document.lines.insert(insert_at, new_line);
+                Some(CaretTarget::Position(CaretPosition {
+                    line: insert_at,
+                    column: 0,
+                }))
+            }
+            EditCommand::InsertLines {
+                at,
+                indent,
+                fragment,
+            } => {
+                if fragment.is_empty() || *at > document.lines.len() {
+                    return None;
+                }
+                let new_lines: Vec<Line> = fragment
+                    .iter()
+                    .map(|(level, text)| Line {
+                        id: document.next_line_id(),
+                        indent: indent + level,
+                        text: text.clone(),
+                        collapsed: false,
+                        source_indent: None,
+                    })
+                    .collect();
+                document.lines.splice(*at..*at, new_lines);
+                Some(CaretTarget::Position(CaretPosition {
+                    line: *at,
+                    column: 0,
+                }))
+            }
+            EditCommand::ToggleCheckbox { line } => {
+                let target = document.lines.get_mut(*line)?;
+                if target.text.starts_with("[ ]") {
+                    target.text = target.text.replacen("[ ]", "[x]", 1);
+                } else if target.text.starts_with("[x]") {
+                    target.text = target.text.replacen("[x]", "[ ]", 1);
+                } else if target.text.starts_with("[X]") {
+                    target.text = target.text.replacen("[X]", "[ ]", 1);
+                } else {
+                    return None;
+                }
+                Some(CaretTarget::Keep)
+            }
+            EditCommand::ToggleFold { line } => {
+                let has_children = document.has_children(*line);
+                let target = document.lines.get_mut(*line)?;
+                if !has_children && !target.collapsed {
+                    return None;
+                }
+                target.collapsed = !target.collapsed;
+                Some(CaretTarget::Keep)
+            }
+            EditCommand::CompressIndentJumps => document
+                .compress_indent_jumps()
+                .then_some(CaretTarget::Keep),
+            EditCommand::FoldToLevel { level } => fold_target(document.fold_to_level(*level)),
+            EditCommand::ToggleFoldAll => fold_target(document.toggle_fold_all()),
+            EditCommand::FoldSubtree { line } => fold_target(document.fold_subtree(*line)),
+            EditCommand::SetFolds { folds } => fold_target(document.set_folds(folds)),
+            EditCommand::ReplaceLines {
+                start,
+                removed,
+                inserted,
+            } => {
+                let end = start + removed.len();
+                if end > document.lines.len() || (removed.is_empty() && inserted.is_empty()) {
+                    return None;
+                }
+                document.lines.splice(*start..end, inserted.iter().cloned());
+                let caret_line = (*start).min(document.lines.len().saturating_sub(1));
+                Some(CaretTarget::Line(caret_line))
+            }
+        }
+    }
+
+    // Returns the command that undoes `self`. It must be computed against the
+    // document as it was *before* `self` is applied.
+    pub fn invert(&self, document: &Document) -> EditCommand {
+        match self {
+            // Apply clamps the column to the end of the line, so the inverse
+            // has to delete from the same clamped spot.
+            EditCommand::InsertText { line, column, text } => EditCommand::DeleteText {
+                line: *line,
+                column: document
+                    .lines
+                    .get(*line)
+                    .map(|target| (*column).min(target.text.chars().count()))
+                    .unwrap_or(*column),
+                len: text.chars().count(),
+            },
+            EditCommand::DeleteText { line, column, len } => {
+                let text = document
+                    .lines
+                    .get(*line)
+                    .map(|target| target.text.chars().skip(*column).take(*len).collect())
+                    .unwrap_or_default();
+                EditCommand::InsertText {
+                    line: *line,
+                    column: *column,
+                    text,
+                }
+            }
+            EditCommand::SetText { line, .. } | EditCommand::ToggleCheckbox { line } => {
+                EditCommand::SetText {
+                    line: *line,
+                    text: document
+                        .lines
+                        .get(*line)
+                        .map(|target| target.text.clone())
+                        .unwrap_or_default(),
+                }
+            }
+            EditCommand::SplitLine { line, column } => {
+                if *line >= document.lines.len() {
+                    return noop_command();
+                }
+                let insert_at = split_destination(document, *line, *column);
+                if insert_at == line + 1 {
+                    return EditCommand::MergeWithPrevious { line: line + 1 };
+                }
+                let window = *line..document.subtree_range(LineKey::Index(*line)).end;
+                let len = window.len() + 1;
+                self.window_inverse(document, window, len)
+            }
+            EditCommand::ToggleFold { .. } => self.clone(),
+            EditCommand::FoldToLevel { .. }
+            | EditCommand::ToggleFoldAll
+            | EditCommand::FoldSubtree { .. }
+            | EditCommand::SetFolds { .. } => {
+                let mut applied = document.clone();
+                let previous = match self {
+                    EditCommand::FoldToLevel { level } => applied.fold_to_level(*level),
+                    EditCommand::ToggleFoldAll => applied.toggle_fold_all(),
+                    EditCommand::FoldSubtree { line } => applied.fold_subtree(*line),
+                    EditCommand::SetFolds { folds } => applied.set_folds(folds),
+                    _ => unreachable!(),
+                };
+                EditCommand::SetFolds { folds: previous }
+            }
+            EditCommand::Indent { lines } => EditCommand::Dedent {
+                lines: lines.clone(),
+            },
+            EditCommand::MoveLine { line, direction } => {
+                match plan_line_move(document, *line, *direction) {
+                    Some(plan) => {
+                        let window = plan.window();
+                        let len = window.len();
+                        self.window_inverse(document, window, len)
+                    }
+                    None => noop_command(),
+                }
+            }
+            EditCommand::ReplaceLines {
+                start,
+                removed,
+                inserted,
+            } => EditCommand::ReplaceLines {
+                start: *start,
+                removed: inserted.clone(),
+                inserted: removed.clone(),
+            },
+            EditCommand::MergeWithPrevious { line } => {
+                if *line == 0 || *line >= document.lines.len() {
+                    return noop_command();
+                }
+                let end = document.subtree_range(LineKey::Index(*line)).end;
+                self.window_inverse(document, line - 1..end, end - line)
+            }
+            EditCommand::DeleteSubtree { line } => {
+                if *line >= document.lines.len() {
+                    return noop_command();
+                }
+                let range = document.subtree_range(LineKey::Index(*line));
+                let after_len = usize::from(range.len() == document.lines.len());
+                self.window_inverse(document, range, after_len)
+            }
+            EditCommand::MergeWithNext { line } => {
+                if line + 1 >= document.lines.len() {
+                    return noop_command();
+                }
+                let end = document.subtree_range(LineKey::Index(line + 1)).end;
+                self.window_inverse(document, *line..end, end - line - 1)
+            }
+            EditCommand::DeleteRange { start, end } => {
+                if start >= end || end.line >= document.lines.len() {
+                    return noop_command();
+                }
+                let window_end = document.subtree_range(LineKey::Index(end.line)).end;
+                let after_len = window_end - end.line;
+                self.window_inverse(document, start.line..window_end, after_len)
+            }
+            EditCommand::InsertFragment { line, fragment, .. } => {
+                let end = (line + 1).min(document.lines.len());
+                let start = (*line).min(end);
+                self.window_inverse(document, start..end, fragment.len().max(1))
+            }
+            EditCommand::Dedent { lines } => {
+                let end = lines.end.min(document.lines.len());
+                let start = lines.start.min(end);
+                self.window_inverse(document, start..end, end - start)
+            }
+            EditCommand::MoveSubtree { line, direction } => {
+                match plan_subtree_move(document, *line, *direction) {
+                    Some(plan) => {
+                        let window = plan.window();
+                        let len = window.len();
+                        self.window_inverse(document, window, len)
+                    }
+                    None => noop_command(),
+                }
+            }
+            EditCommand::DuplicateSubtree { line, direction } => {
+                if *line >= document.lines.len() {
+                    return noop_command();
+                }
+                let range = document.subtree_range(LineKey::Index(*line));
+                let insert_at = match direction {
+                    MoveDirection::Up => range.start,
+                    MoveDirection::Down => range.end,
+                };
+                self.window_inverse(document, insert_at..insert_at, range.len())
+            }
+            EditCommand::InsertRootLine { line } => {
+                let insert_at = (line + 1).min(document.lines.len());
+                self.window_inverse(document, insert_at..insert_at, 1)
+            }
+            EditCommand::InsertLines { at, fragment, .. } => {
+                let at = (*at).min(document.lines.len());
+                self.window_inverse(document, at..at, fragment.len())
+            }
+            EditCommand::CompressIndentJumps => {
+                let len = document.lines.len();
+                self.window_inverse(document, 0..len, len)
+            }
+        }
+    }
+
+    // Builds a `ReplaceLines` inverse for commands whose effect is confined to
+    // `window` in the old document and `after_len` lines in the new one.
+    // Cloning the document is cheap because `Lines` shares unchanged chunks.
+    fn window_inverse(
+        &self,
+        document: &Document,
+        window: Range<usize>,
+        after_len: usize,
+    ) -> EditCommand {
+        let mut applied = document.clone();
+        if self.apply(&mut applied).is_none() {
+            return noop_command();
+        }
+
+        EditCommand::ReplaceLines {
+            start: window.start,
+            removed: applied
+                .lines
+                .range_to_vec(window.start..window.start + after_len),
+            inserted: document.lines.range_to_vec(window),
+        }
+    }
+}
+
+// Index at which `SplitLine` inserts the new line.
+fn split_destination(document: &Document, line: usize, column: usize) -> usize {
+    let current = &document.lines[line];
+    if !current.collapsed || !document.has_children(line) {
+        return line + 1;
+    }
+    if column == 0 && !current.text.is_empty() {
+        line
+    } else {
+        document.subtree_range(LineKey::Index(line)).end
+    }
+}
+
+// Appends the text of `line` to the line above and removes it. The removed
+// line's descendants are shifted by the indent difference so they become
+// children of the merged line instead of being re-parented to whatever happens
+// to precede them. The upper line keeps its id and indent. Returns the column
+// where the two texts meet.
+fn merge_into_previous(document: &mut Document, line: usize) -> usize {
+    let descendants_end = document.subtree_range(LineKey::Index(line)).end - 1;
+    let lower = document.lines.remove(line);
+    let upper = &mut document.lines[line - 1];
+    let column = upper.text.chars().count();
+    upper.text.push_str(&lower.text);
+
+    let (from, to) = (lower.indent, upper.indent);
+    for index in line..descendants_end {
+        let child = &mut document.lines[index];
+        child.indent = (child.indent + to).saturating_sub(from);
+    }
+    column
+}
+
+fn fold_target(changes: Vec<(usize, bool)>) -> Option<CaretTarget> {
+    (!changes.is_empty()).then_some(CaretTarget::Keep)
+}
+
+fn noop_command() -> EditCommand {
+    EditCommand::ReplaceLines {
+        start: 0,
+        removed: Vec::new(),
+        inserted: Vec::new(),
+    }
+}
+
+struct SubtreeMove {
+    block: Range<usize>,
+    sibling: Range<usize>,
+    destination: usize,
+}
+
+impl SubtreeMove {
+    fn window(&self) -> Range<usize> {
+        self.block.start.min(self.sibling.start)..self.block.end.max(self.sibling.end)
+    }
+
+    fn apply(&self, document: &mut Document) -> CaretTarget {
+        let block: Vec<Line> = document.lines.drain(self.block.clone()).collect();
+        document
+            .lines
+            .splice(self.destination..self.destination, block);
+        CaretTarget::Line(self.destination)
+    }
+}
+
+// Plans a single-line move over the neighbouring *visible* line. A folded
+// neighbour is stepped over as a whole so the line never ends up hidden
+// inside it, and a folded line carries its hidden block along.
+fn plan_line_move(
+    document: &Document,
+    line: usize,
+    direction: MoveDirection,
+) -> Option<SubtreeMove> {
+    let block = visible_block(document, line)?;
+
+    match direction {
+        MoveDirection::Up => {
+            let previous = document.visible_ancestor(block.start.checked_sub(1)?);
+            Some(SubtreeMove {
+                sibling: previous..block.start,
+                block,
+                destination: previous,
+            })
+        }
+        MoveDirection::Down => {
+            let sibling = visible_block(document, block.end)?;
+            let destination = sibling.end - block.len();
+            Some(SubtreeMove {
+                block,
+                sibling,
+                destination,
+            })
+        }
+    }
+}
+
+// The line itself, or the line and its hidden descendants when it is folded.
+fn visible_block(document: &Document, line: usize) -> Option<Range<usize>> {
+    let target = document.lines.get(line)?;
+    Some(if target.collapsed {
+        document.subtree_range(LineKey::Index(line))
+    } else {
+        line..line + 1
+    })
+}
+
+fn plan_subtree_move(
+    document: &Document,
+    line: usize,
+    direction: MoveDirection,
+) -> Option<SubtreeMove> {
+    if line >= document.lines.len() {
+        return None;
+    }
+
+    let block = document.subtree_range(LineKey::Index(line));
+    let base_indent = document.lines[line].indent;
+    let same_level = |sibling: &usize| document.lines[*sibling].indent == base_indent;
+
+    match direction {
+        MoveDirection::Up => {
+            let previous_start = document
+                .prev_sibling(LineKey::Index(line))
+                .filter(same_level)?;
+            Some(SubtreeMove {
+                sibling: previous_start..block.start,
+                block,
+                destination: previous_start,
+            })
+        }
+        MoveDirection::Down => {
+            let next_start = document
+                .next_sibling(LineKey::Index(line))
+                .filter(same_level)?;
+            let sibling = document.subtree_range(LineKey::Index(next_start));
+            let destination = next_start - block.len() + sibling.len();
+            Some(SubtreeMove {
+                block,
+                sibling,
+                destination,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(text: &str, command: EditCommand) -> (String, Option<CaretTarget>) {
+        let mut document = Document::from_text(text);
+        let target = command.apply(&mut document);
+        (document.to_text(), target)
+    }
+
+    // Applies `command`, then its inverse, and checks that the document is
+    // back to the original lines; applying the command again must give the
+    // same text as the first time.
+    fn assert_round_trip(document: &Document, command: EditCommand) {
+        let inverse = command.invert(document);
+        let mut edited = document.clone();
+        assert!(
+            command.apply(&mut edited).is_some(),
+            "{command:?} did nothing"
+        );
+        let once = edited.to_text();
+
+        inverse.apply(&mut edited);
+        assert_eq!(edited.lines, document.lines, "inverse of {command:?}");
+
+        command.apply(&mut edited);
+        assert_eq!(edited.to_text(), once, "replay of {command:?}");
+    }
+
+    fn position(line: usize, column: usize) -> CaretTarget {
+        CaretTarget::Position(CaretPosition { line, column })
+    }
+
+    // B-02: the new line keeps the indent and takes the text after the caret.
+    #[test]
+    fn b02_split_line_keeps_indent() {
+        let (text, target) = applied("\tabcd", EditCommand::SplitLine { line: 0, column: 2 });
+        assert_eq!(text, "\tab\n\tcd");
+        assert_eq!(target, Some(position(1, 0)));
+    }
+
+    // E-206: Enter at the end of a collapsed parent adds a sibling after its
+    // hidden children.
+    #[test]
+    fn e206_split_collapsed_parent_adds_sibling_after_subtree() {
+        let mut document = Document::from_text("a\n\tb\nc");
+        document.lines[0].collapsed = true;
+        let target = EditCommand::SplitLine { line: 0, column: 1 }.apply(&mut document);
+        assert_eq!(document.to_text(), "a\n\tb\n\nc");
+        assert_eq!(target, Some(position(2, 0)));
+        assert!(document.lines[0].collapsed);
+    }
+
+    // B-03 / E-102: the merged line's children move under the line above.
+    #[test]
+    fn e102_merge_keeps_children_under_the_merged_line() {
+        let (text, target) = applied(
+            "a\n\tb\n\t\tc\n\t\t\td",
+            EditCommand::MergeWithPrevious { line: 2 },
+        );
+        assert_eq!(text, "a\n\tbc\n\t\td");
+        assert_eq!(target, Some(position(1, 1)));
+    }
+
+    #[test]
+    fn merge_with_next_pulls_up_its_children() {
+        let (text, _) = applied("a\nb\n\tc", EditCommand::MergeWithNext { line: 0 });
+        assert_eq!(text, "ab\n\tc");
+    }
+
+    #[test]
+    fn merge_keeps_the_upper_line_id() {
+        let mut document = Document::from_text("a\nb");
+        let id = document.lines[0].id;
+        EditCommand::MergeWithNext { line: 0 }.apply(&mut document);
+        assert_eq!(document.lines[0].id, id);
+    }
+
+    #[test]
+    fn delete_range_across_lines_joins_the_ends() {
+        let (text, target) = applied(
+            "abc\n\tdef\nghi\n\tkid",
+            EditCommand::DeleteRange {
+                start: CaretPosition { line: 0, column: 1 },
+                end: CaretPosition { line: 2, column: 1 },
+            },
+        );
+        assert_eq!(text, "ahi\n\tkid");
+        assert_eq!(target, Some(position(0, 1)));
+    }
+
+    #[test]
+    fn insert_fragment_reindents_below_the_caret_line() {
+        let (text, target) = applied(
+            "\tab",
+            EditCommand::InsertFragment {
+                line: 0,
+                column: 1,
+                fragment: vec![
+                    (0, "X".to_string()),
+                    (1, "Y".to_string()),
+                    (0, "Z".to_string()),
+                ],
+            },
+        );
+        assert_eq!(text, "\taX\n\t\tY\n\tZb");
+        assert_eq!(target, Some(position(2, 1)));
+    }
+
+    #[test]
+    fn delete_subtree_removes_descendants() {
+        let (text, target) = applied("a\n\tb\n\t\tc\nd", EditCommand::DeleteSubtree { line: 0 });
+        assert_eq!(text, "d");
+        assert_eq!(target, Some(CaretTarget::Line(0)));
+    }
+
+    #[test]
+    fn deleting_the_whole_outline_leaves_an_empty_line() {
+        let (text, _) = applied("a\n\tb", EditCommand::DeleteSubtree { line: 0 });
+        assert_eq!(text, "");
+    }
+
+    // E-501 / E-502: every selected line shifts by one level.
+    #[test]
+    fn e501_e502_indent_and_dedent_ranges() {
+        let (text, _) = applied("x\n\ta\n\t\tb\n\tc", EditCommand::Indent { lines: 1..4 });
+        assert_eq!(text, "x\n\t\ta\n\t\t\tb\n\t\tc");
+        let (text, _) = applied("x\n\ta\n\t\tb\n\tc", EditCommand::Dedent { lines: 1..4 });
+        assert_eq!(text, "x\na\n\tb\nc");
+    }
+
+    // M-01: a single line swaps with its visible neighbour.
+    #[test]
+    fn m01_move_line_swaps_neighbours() {
+        let (text, target) = applied(
+            "a\nb\nc",
+            EditCommand::MoveLine {
+                line: 1,
+                direction: MoveDirection::Up,
+            },
+        );
+        assert_eq!(text, "b\na\nc");
+        assert_eq!(target, Some(CaretTarget::Line(0)));
+    }
+
+    #[test]
+    fn move_line_steps_over_a_folded_block() {
+        let mut document = Document::from_text("a\nb\n\tc\nd");
+        document.lines[1].collapsed = true;
+        EditCommand::MoveLine {
+            line: 0,
+            direction: MoveDirection::Down,
+        }
+        .apply(&mut document);
+        assert_eq!(document.to_text(), "b\n\tc\na\nd");
+    }
+
+    // M-02: the subtree moves past its sibling's whole subtree.
+    #[test]
+    fn m02_move_subtree_keeps_structure() {
+        let (text, target) = applied(
+            "a\n\ta1\nb\n\tb1\n\t\tb2",
+            EditCommand::MoveSubtree {
+                line: 0,
+                direction: MoveDirection::Down,
+            },
+        );
+        assert_eq!(text, "b\n\tb1\n\t\tb2\na\n\ta1");
+        assert_eq!(target, Some(CaretTarget::Line(3)));
+    }
+
+    // E-103: moving past the first or last sibling does nothing.
+    #[test]
+    fn e103_move_subtree_at_the_edges_is_a_no_op() {
+        let up = EditCommand::MoveSubtree {
+            line: 0,
+            direction: MoveDirection::Up,
+        };
+        assert_eq!(applied("a\nb", up).1, None);
+        let down = EditCommand::MoveSubtree {
+            line: 1,
+            direction: MoveDirection::Down,
+        };
+        assert_eq!(applied("a\nb", down).1, None);
+    }
+
+    // M-04: the copy gets fresh ids and keeps fold state.
+    #[test]
+    fn m04_duplicate_subtree() {
+        let mut document = Document::from_text("a\n\tb\nc");
+        document.lines[0].collapsed = true;
+        EditCommand::DuplicateSubtree {
+            line: 0,
+            direction: MoveDirection::Down,
+        }
+        .apply(&mut document);
+        assert_eq!(document.to_text(), "a\n\tb\na\n\tb\nc");
+        assert!(document.lines[2].collapsed);
+        assert_ne!(document.lines[2].id, document.lines[0].id);
+    }
+
+    // M-05: Shift+Enter inserts a level 0 line below.
+    #[test]
+    fn m05_insert_root_line() {
+        let (text, target) = applied("a\n\t\tb", EditCommand::InsertRootLine { line: 1 });
+        assert_eq!(text, "a\n\t\tb\n");
+        assert_eq!(target, Some(position(2, 0)));
+    }
+
+    #[test]
+    fn insert_lines_indents_relative_to_the_given_level() {
+        let (text, _) = applied(
+            "a\n\tb",
+            EditCommand::InsertLines {
+                at: 2,
+                indent: 1,
+                fragment: vec![(0, "c".to_string()), (1, "d".to_string())],
+            },
+        );
+        assert_eq!(text, "a\n\tb\n\tc\n\t\td");
+    }
+
+    #[test]
+    fn text_edits_do_not_touch_the_structure_version() {
+        let mut document = Document::from_text("a");
+        let version = document.structure_version;
+        EditCommand::InsertText {
+            line: 0,
+            column: 1,
+            text: "b".to_string(),
+        }
+        .apply(&mut document);
+        assert_eq!(document.structure_version, version);
+        EditCommand::SplitLine { line: 0, column: 1 }.apply(&mut document);
+        assert_ne!(document.structure_version, version);
+    }
+
+    #[test]
+    fn apply_invert_apply_round_trips() {
+        let text = "a\n\t[ ] b\n\t\tc\n\td\n\t\t\t\te\nf\n\tg";
+        let mut document = Document::from_text(text);
+        document.lines[5].collapsed = true;
+        let at = |line, column| CaretPosition { line, column };
+        let fragment = vec![(0, "p".to_string()), (1, "q".to_string())];
+        let commands = [
+            EditCommand::InsertText {
+                line: 1,
+                column: 1,
+                text: "zz".to_string(),
+            },
+            EditCommand::InsertText {
+                line: 0,
+                column: 99,
+                text: "X".to_string(),
+            },
+            EditCommand::DeleteText {
+                line: 1,
+                column: 0,
+                len: 2,
+            },
+            EditCommand::SetText {
+                line: 0,
+                text: "new".to_string(),
+            },
+            EditCommand::SplitLine { line: 1, column: 2 },
+            EditCommand::SplitLine { line: 5, column: 1 },
+            EditCommand::SplitLine { line: 5, column: 0 },
+            EditCommand::DeleteRange {
+                start: at(0, 1),
+                end: at(2, 1),
+            },
+            EditCommand::InsertFragment {
+                line: 2,
+                column: 1,
+                fragment: fragment.clone(),
+            },
+            EditCommand::MergeWithPrevious { line: 3 },
+            EditCommand::MergeWithNext { line: 0 },
+            EditCommand::DeleteSubtree { line: 1 },
+            EditCommand::Indent { lines: 3..5 },
+            EditCommand::Dedent { lines: 1..4 },
+            EditCommand::MoveLine {
+                line: 3,
+                direction: MoveDirection::Up,
+            },
+            EditCommand::MoveSubtree {
+                line: 5,
+                direction: MoveDirection::Up,
+            },
+            EditCommand::DuplicateSubtree {
+                line: 1,
+                direction: MoveDirection::Down,
+            },
+            EditCommand::InsertRootLine { line: 2 },
+            EditCommand::InsertLines {
+                at: 4,
+                indent: 1,
+                fragment,
+            },
+            EditCommand::ToggleCheckbox { line: 1 },
+            EditCommand::ToggleFold { line: 0 },
+            EditCommand::FoldToLevel { level: 1 },
+            EditCommand::ToggleFoldAll,
+            EditCommand::FoldSubtree { line: 0 },
+            EditCommand::SetFolds {
+                folds: vec![(1, true), (5, false)],
+            },
+            EditCommand::CompressIndentJumps,
+        ];
+        for command in commands {
+            assert_round_trip(&document, command);
+        }
+    }
+
+    #[test]
+    fn whole_document_delete_round_trips() {
+        let document = Document::from_text("a\n\tb");
+        assert_round_trip(&document, EditCommand::DeleteSubtree { line: 0 });
+    }
+}
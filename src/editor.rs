@@ -0,0 +1,1300 @@
+use crate::command::{CaretTarget, EditCommand, MoveDirection};
+use crate::history::History;
+use crate::kill_ring::KillRing;
+use crate::model::{
+    CaretPosition, Document, LineKey, SelectionRange, next_grapheme_boundary, next_word_boundary,
+    prev_grapheme_boundary, prev_word_boundary,
+};
+use std::ops::Range;
+
+// Editing engine without any Dioxus dependency. The `Outliner` component keeps
+// one of these in a signal and forwards DOM events to the methods below.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EditorState {
+    pub document: Document,
+    pub selection: SelectionRange,
+    pub history: History,
+    // Column that vertical movement aims for. It survives passing through
+    // shorter lines and is cleared by any other caret change.
+    pub goal_column: Option<usize>,
+    pub kill_ring: KillRing,
+    // While set, caret movements extend the selection from its anchor, which
+    // acts as the Emacs mark.
+    pub mark_active: bool,
+}
+
+impl EditorState {
+    pub fn new(document: Document) -> Self {
+        let selection = SelectionRange::caret(0, 0);
+        let history = History::new(document.clone(), selection.clone());
+        Self {
+            document,
+            selection,
+            history,
+            goal_column: None,
+            kill_ring: KillRing::default(),
+            mark_active: false,
+        }
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        Self::new(Document::from_text(text))
+    }
+
+    pub fn to_text(&self) -> String {
+        self.document.to_text()
+    }
+
+    pub fn set_caret(&mut self, line_index: usize, column: usize) {
+        self.set_selection(SelectionRange::caret(line_index, column));
+    }
+
+    pub fn set_selection(&mut self, selection: SelectionRange) {
+        self.selection = selection;
+        self.goal_column = None;
+        self.kill_ring.interrupt();
+        self.mark_active = false;
+    }
+
+    pub fn caret_column(&self, line_index: usize) -> usize {
+        if self.selection.focus.line == line_index {
+            self.selection.focus.column
+        } else {
+            self.document
+                .lines
+                .get(line_index)
+                .map(|line| line.text.chars().count())
+                .unwrap_or(0)
+        }
+    }
+
+    pub fn selected_line_range(&self) -> Range<usize> {
+        if self.document.lines.is_empty() {
+            return 0..0;
+        }
+
+        let (start, end) = self.selection.normalized();
+        let start_line = start.line.min(self.document.lines.len() - 1);
+        let end_line = end.line.min(self.document.lines.len() - 1);
+
+        start_line..(end_line + 1)
+    }
+
+    pub fn clamp_caret_column(&self, line_index: usize, desired_column: usize) -> usize {
+        self.document
+            .lines
+            .get(line_index)
+            .map(|line| desired_column.min(line.text.chars().count()))
+            .unwrap_or(0)
+    }
+
+    pub fn focus_parent(&mut self, line_index: usize) -> bool {
+        let Some(parent) = self.document.parent_of(LineKey::Index(line_index)) else {
+            return false;
+        };
+        let column = self.clamp_caret_column(parent, self.caret_column(line_index));
+        self.set_caret(parent, column);
+        true
+    }
+
+    // Folded children stay hidden, so a collapsed line has nowhere to go.
+    pub fn focus_first_child(&mut self, line_index: usize) -> bool {
+        if self
+            .document
+            .lines
+            .get(line_index)
+            .is_none_or(|line| line.collapsed)
+        {
+            return false;
+        }
+        let Some(child) = self.document.first_child(LineKey::Index(line_index)) else {
+            return false;
+        };
+        let column = self.clamp_caret_column(child, self.caret_column(line_index));
+        self.set_caret(child, column);
+        true
+    }
+
+    // Applies `command`, moves the caret and records a history node. Returns
+    // whether the document changed.
+    pub fn execute(&mut self, command: EditCommand, caret_column: usize) -> bool {
+        self.execute_all(vec![command], caret_column)
+    }
+
+    // Applies `commands` in order as a single undo step. Each command sees
+    // the document left by the previous one. In strict hierarchy mode the
+    // whole step is rolled back through the commands' inverses if it leaves
+    // an indent jump behind.
+    pub fn execute_all(&mut self, commands: Vec<EditCommand>, caret_column: usize) -> bool {
+        let selection_before = self.selection.clone();
+        let strict = self.document.strict_hierarchy;
+        let mut inverses = Vec::new();
+        let mut changed = false;
+        for command in commands {
+            let inverse = strict.then(|| command.invert(&self.document));
+            let Some(target) = command.apply(&mut self.document) else {
+                continue;
+            };
+            inverses.extend(inverse);
+            changed = true;
+            match target {
+                CaretTarget::Keep => {}
+                CaretTarget::Line(line) => {
+                    let column = self.clamp_caret_column(line, caret_column);
+                    self.set_caret(line, column);
+                }
+                CaretTarget::Position(position) => self.set_caret(position.line, position.column),
+            }
+        }
+        if !changed {
+            return false;
+        }
+        if strict && self.document.has_indent_jumps() {
+            for inverse in inverses.into_iter().rev() {
+                inverse.apply(&mut self.document);
+            }
+            self.set_selection(selection_before);
+            return false;
+        }
+        self.reveal_caret();
+
+        self.history.record(
+            self.document.clone(),
+            selection_before,
+            self.selection.clone(),
+        );
+        true
+    }
+
+    // Replaces the text of a line as typed by the user. Consecutive input on
+    // the same line collapses into one undo step.
+    pub fn input(&mut self, line_index: usize, text: String, caret_column: usize) {
+        let selection_before = self.selection.clone();
+        let command = EditCommand::SetText {
+            line: line_index,
+            text,
+        };
+        command.apply(&mut self.document);
+        self.set_caret(line_index, caret_column);
+
+        self.history.record_typing(
+            line_index,
+            self.document.clone(),
+            selection_before,
+            self.selection.clone(),
+        );
+    }
+
+    // Splits the caret line, replacing the selection first if there is one.
+    pub fn enter(&mut self, line_index: usize) -> bool {
+        if !self.selection.is_collapsed() {
+            let (start, end) = self.selection.normalized();
+            return self.execute_all(
+                vec![
+                    EditCommand::DeleteRange { start, end },
+                    EditCommand::SplitLine {
+                        line: start.line,
+                        column: start.column,
+                    },
+                ],
+                0,
+            );
+        }
+
+        let column = self.caret_column(line_index);
+        self.execute(
+            EditCommand::SplitLine {
+                line: line_index,
+                column,
+            },
+            column,
+        )
+    }
+
+    // Removes the selected text, joining the first and last selected lines.
+    pub fn delete_selection(&mut self) -> bool {
+        let (start, end) = self.selection.normalized();
+        if start == end {
+            return false;
+        }
+        self.execute(EditCommand::DeleteRange { start, end }, start.column)
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection.normalized();
+        if start == end {
+            return None;
+        }
+        let fragment = self.document.fragment(start, end);
+        Some(self.document.fragment_to_text(&fragment))
+    }
+
+    // Inserts a parsed fragment at the caret, replacing the selection. Pasted
+    // lines keep their relative indentation below the caret line's indent.
+    pub fn paste(&mut self, line_index: usize, fragment: Vec<(u32, String)>) -> bool {
+        let (start, end) = if self.selection.is_collapsed() {
+            let caret = self.focus_on(line_index);
+            (caret, caret)
+        } else {
+            self.selection.normalized()
+        };
+        self.execute_all(
+            vec![
+                EditCommand::DeleteRange { start, end },
+                EditCommand::InsertFragment {
+                    line: start.line,
+                    column: start.column,
+                    fragment,
+                },
+            ],
+            start.column,
+        )
+    }
+
+    // Returns the native selection of a line's input, given in chars, when it
+    // differs from the model. A selection spanning several lines is kept.
+    // The DOM always reports `start <= end`, so only the normalized ranges are
+    // compared and a matching backward selection keeps its direction.
+    pub fn selection_from_dom(
+        &self,
+        line_index: usize,
+        start: usize,
+        end: usize,
+    ) -> Option<SelectionRange> {
+        if self.selection.spans_lines() && self.selection.focus.line == line_index {
+            return None;
+        }
+        let (start, end) = (start.min(end), start.max(end));
+        let (model_start, model_end) = self.selection.normalized();
+        if model_start.line == line_index
+            && model_end.line == line_index
+            && (model_start.column, model_end.column) == (start, end)
+        {
+            return None;
+        }
+        Some(SelectionRange {
+            anchor: CaretPosition {
+                line: line_index,
+                column: start,
+            },
+            focus: CaretPosition {
+                line: line_index,
+                column: end,
+            },
+        })
+    }
+
+    // Moves the caret one grapheme cluster to the left, wrapping to the end of
+    // the previous visible line.
+    pub fn move_caret_left(&mut self, line_index: usize) -> bool {
+        let (start, end) = self.selection.normalized();
+        if start != end {
+            self.set_caret(start.line, start.column);
+            return true;
+        }
+
+        let Some(target) = self.grapheme_left(self.focus_on(line_index)) else {
+            return false;
+        };
+        self.set_caret(target.line, target.column);
+        true
+    }
+
+    // Moves the caret one grapheme cluster to the right, wrapping to the start
+    // of the next visible line.
+    pub fn move_caret_right(&mut self, line_index: usize) -> bool {
+        let (start, end) = self.selection.normalized();
+        if start != end {
+            self.set_caret(end.line, end.column);
+            return true;
+        }
+
+        let Some(target) = self.grapheme_right(self.focus_on(line_index)) else {
+            return false;
+        };
+        self.set_caret(target.line, target.column);
+        true
+    }
+
+    // Moves only the focus end of the selection; the anchor stays put.
+    pub fn extend_selection_to(&mut self, line_index: usize, column: usize) {
+        self.selection.focus = CaretPosition {
+            line: line_index,
+            column: self.clamp_caret_column(line_index, column),
+        };
+        self.goal_column = None;
+    }
+
+    pub fn extend_left(&mut self, line_index: usize) -> bool {
+        let Some(target) = self.grapheme_left(self.focus_on(line_index)) else {
+            return false;
+        };
+        self.extend_selection_to(target.line, target.column);
+        true
+    }
+
+    pub fn extend_right(&mut self, line_index: usize) -> bool {
+        let Some(target) = self.grapheme_right(self.focus_on(line_index)) else {
+            return false;
+        };
+        self.extend_selection_to(target.line, target.column);
+        true
+    }
+
+    // Moves the caret to the previous word start or next word end, wrapping
+    // to the neighbouring visible line at either end of a line.
+    pub fn move_word(&mut self, line_index: usize, forward: bool, extend: bool) -> bool {
+        let focus = self.focus_on(line_index);
+        let Some(text) = self.document.lines.get(line_index).map(|line| &line.text) else {
+            return false;
+        };
+        let length = text.chars().count();
+
+        let target = if forward && focus.column < length {
+            CaretPosition {
+                line: line_index,
+                column: next_word_boundary(text, focus.column),
+            }
+        } else if !forward && focus.column > 0 {
+            CaretPosition {
+                line: line_index,
+                column: prev_word_boundary(text, focus.column),
+            }
+        } else {
+            let wrapped = if forward {
+                self.grapheme_right(focus)
+            } else {
+                self.grapheme_left(focus)
+            };
+            let Some(target) = wrapped else {
+                return false;
+            };
+            target
+        };
+        self.place_focus(target, extend);
+        true
+    }
+
+    // Moves the caret `count` visible lines up or down, aiming for the goal
+    // column. Running out of lines moves to the start or end of the outline's
+    // first or last visible line. With `extend` only the focus moves.
+    pub fn move_vertically(
+        &mut self,
+        line_index: usize,
+        direction: MoveDirection,
+        count: usize,
+        extend: bool,
+    ) -> bool {
+        let focus = self.focus_on(line_index);
+        let goal = self.goal_column.unwrap_or(focus.column);
+
+        let mut line = line_index;
+        let mut moved = 0;
+        while moved < count {
+            let neighbour = match direction {
+                MoveDirection::Up => self.previous_visible_line(line),
+                MoveDirection::Down => self.next_visible_line(line),
+            };
+            let Some(neighbour) = neighbour else {
+                break;
+            };
+            line = neighbour;
+            moved += 1;
+        }
+
+        let (target, goal) = if moved < count {
+            let column = match direction {
+                MoveDirection::Up => 0,
+                MoveDirection::Down => self.clamp_caret_column(line, usize::MAX),
+            };
+            (CaretPosition { line, column }, None)
+        } else {
+            let text = &self.document.lines[line].text;
+            let column = prev_grapheme_boundary(text, goal.saturating_add(1));
+            (CaretPosition { line, column }, Some(goal))
+        };
+        if target == focus && (extend || self.selection.is_collapsed()) {
+            return false;
+        }
+
+        self.place_focus(target, extend);
+        self.goal_column = goal;
+        true
+    }
+
+    // Home / End: the start or end of the caret line.
+    pub fn move_to_line_boundary(&mut self, line_index: usize, end: bool, extend: bool) -> bool {
+        let column = if end {
+            self.clamp_caret_column(line_index, usize::MAX)
+        } else {
+            0
+        };
+        self.place_focus(
+            CaretPosition {
+                line: line_index,
+                column,
+            },
+            extend,
+        );
+        true
+    }
+
+    // Ctrl+Home / Ctrl+End: the start of the outline or the end of its last
+    // visible line.
+    pub fn move_to_document_boundary(&mut self, end: bool, extend: bool) -> bool {
+        let Some(last) = self.document.lines.len().checked_sub(1) else {
+            return false;
+        };
+        let target = if end {
+            let line = self.document.visible_ancestor(last);
+            CaretPosition {
+                line,
+                column: self.clamp_caret_column(line, usize::MAX),
+            }
+        } else {
+            CaretPosition { line: 0, column: 0 }
+        };
+        self.place_focus(target, extend);
+        true
+    }
+
+    fn place_focus(&mut self, target: CaretPosition, extend: bool) {
+        if extend {
+            self.extend_selection_to(target.line, target.column);
+        } else {
+            self.set_caret(target.line, target.column);
+        }
+    }
+
+    // Selects the whole outline, up to the end of its last line even when a
+    // fold hides it, so deleting the selection leaves nothing behind. The
+    // focus sits at the start because the first line is always rendered,
+    // which keeps the DOM sync from dropping the selection.
+    pub fn select_all(&mut self) -> bool {
+        let Some(last) = self.document.lines.len().checked_sub(1) else {
+            return false;
+        };
+        self.set_caret(last, self.clamp_caret_column(last, usize::MAX));
+        self.extend_selection_to(0, 0);
+        true
+    }
+
+    fn focus_on(&self, line_index: usize) -> CaretPosition {
+        CaretPosition {
+            line: line_index,
+            column: self.caret_column(line_index),
+        }
+    }
+
+    fn grapheme_left(&self, position: CaretPosition) -> Option<CaretPosition> {
+        if position.column > 0 {
+            let text = &self.document.lines.get(position.line)?.text;
+            return Some(CaretPosition {
+                line: position.line,
+                column: prev_grapheme_boundary(text, position.column),
+            });
+        }
+
+        let previous = self.previous_visible_line(position.line)?;
+        Some(CaretPosition {
+            line: previous,
+            column: self.clamp_caret_column(previous, usize::MAX),
+        })
+    }
+
+    fn grapheme_right(&self, position: CaretPosition) -> Option<CaretPosition> {
+        let text = &self.document.lines.get(position.line)?.text;
+        if position.column < text.chars().count() {
+            return Some(CaretPosition {
+                line: position.line,
+                column: next_grapheme_boundary(text, position.column),
+            });
+        }
+
+        let next = self.next_visible_line(position.line)?;
+        Some(CaretPosition {
+            line: next,
+            column: 0,
+        })
+    }
+
+    pub fn previous_visible_line(&self, line_index: usize) -> Option<usize> {
+        let previous = line_index.checked_sub(1)?;
+        Some(self.document.visible_ancestor(previous))
+    }
+
+    pub fn next_visible_line(&self, line_index: usize) -> Option<usize> {
+        let line = self.document.lines.get(line_index)?;
+        let next = if line.collapsed {
+            self.document.subtree_range(LineKey::Index(line_index)).end
+        } else {
+            line_index + 1
+        };
+        (next < self.document.lines.len()).then_some(next)
+    }
+
+    // Deletes the selection, or else the grapheme cluster before the caret.
+    // At column 0 it dedents the line with its subtree, or merges it into the
+    // previous one when already at indent 0.
+    pub fn backspace(&mut self, line_index: usize) -> bool {
+        if self.delete_selection() {
+            return true;
+        }
+        let Some(line) = self.document.lines.get(line_index) else {
+            return false;
+        };
+
+        let column = self.caret_column(line_index);
+        if column > 0 {
+            let start = prev_grapheme_boundary(&line.text, column);
+            return self.execute(
+                EditCommand::DeleteText {
+                    line: line_index,
+                    column: start,
+                    len: column - start,
+                },
+                start,
+            );
+        }
+
+        if line.indent > 0 {
+            return self.adjust_subtree_indent(line_index, false);
+        }
+        // The line above is hidden in a fold; merging into it would bury this
+        // line's text.
+        if self.previous_visible_line(line_index) != line_index.checked_sub(1) {
+            return false;
+        }
+        self.execute(EditCommand::MergeWithPrevious { line: line_index }, 0)
+    }
+
+    // Deletes the selection, or else the grapheme cluster after the caret. At
+    // the end of a line it pulls the next line up into this one. A folded
+    // line is left alone so its hidden children are not merged by accident.
+    pub fn delete_forward(&mut self, line_index: usize) -> bool {
+        if self.delete_selection() {
+            return true;
+        }
+        let Some(line) = self.document.lines.get(line_index) else {
+            return false;
+        };
+
+        let column = self.caret_column(line_index);
+        if column < line.text.chars().count() {
+            let end = next_grapheme_boundary(&line.text, column);
+            return self.execute(
+                EditCommand::DeleteText {
+                    line: line_index,
+                    column,
+                    len: end - column,
+                },
+                column,
+            );
+        }
+
+        if line.collapsed {
+            return false;
+        }
+        self.execute(EditCommand::MergeWithNext { line: line_index }, column)
+    }
+
+    // Removes the line together with all of its descendants.
+    pub fn delete_subtree(&mut self, line_index: usize) -> bool {
+        let column = self.caret_column(line_index);
+        self.execute(EditCommand::DeleteSubtree { line: line_index }, column)
+    }
+
+    // Removes the subtree at the line, plus the following `count - 1`
+    // sibling subtrees, as one undo step.
+    pub fn delete_subtrees(&mut self, line_index: usize, count: usize) -> bool {
+        let column = self.caret_column(line_index);
+        let (_, roots) = self.sibling_run(line_index, count);
+        let commands = vec![EditCommand::DeleteSubtree { line: line_index }; roots];
+        self.execute_all(commands, column)
+    }
+
+    // Copies the lines `delete_subtrees` would remove, with levels relative
+    // to the line.
+    pub fn yank_subtrees(&self, line_index: usize, count: usize) -> Vec<(u32, String)> {
+        let Some(base) = self.document.lines.get(line_index).map(|line| line.indent) else {
+            return Vec::new();
+        };
+        let (end, _) = self.sibling_run(line_index, count);
+        (line_index..end)
+            .map(|index| {
+                let line = &self.document.lines[index];
+                (line.indent - base, line.text.clone())
+            })
+            .collect()
+    }
+
+    // End of up to `count` consecutive same-level subtrees starting at the
+    // line, and how many were found.
+    fn sibling_run(&self, line_index: usize, count: usize) -> (usize, usize) {
+        let Some(base) = self.document.lines.get(line_index).map(|line| line.indent) else {
+            return (line_index, 0);
+        };
+        let mut end = line_index;
+        let mut roots = 0;
+        while roots < count.max(1) {
+            match self.document.lines.get(end) {
+                Some(next) if next.indent == base => {
+                    end = self.document.subtree_range(LineKey::Index(end)).end
+                }
+                _ => break,
+            }
+            roots += 1;
+        }
+        (end, roots)
+    }
+
+    // Inserts yanked lines as siblings of the line, after its subtree or
+    // right before it.
+    pub fn put_lines(
+        &mut self,
+        line_index: usize,
+        fragment: Vec<(u32, String)>,
+        below: bool,
+    ) -> bool {
+        let Some(indent) = self.document.lines.get(line_index).map(|line| line.indent) else {
+            return false;
+        };
+        let at = if below {
+            self.document.subtree_range(LineKey::Index(line_index)).end
+        } else {
+            line_index
+        };
+        self.execute(
+            EditCommand::InsertLines {
+                at,
+                indent,
+                fragment,
+            },
+            0,
+        )
+    }
+
+    // Opens an empty sibling line below the subtree or above the line.
+    pub fn open_sibling(&mut self, line_index: usize, below: bool) -> bool {
+        self.put_lines(line_index, vec![(0, String::new())], below)
+    }
+
+    // Emacs `kill-line`: kills from the caret to the end of the line, or the
+    // whole line with its subtree when the caret is already at the end.
+    pub fn kill_line(&mut self, line_index: usize) -> bool {
+        let append = self.kill_ring.appending();
+        let column = self.caret_column(line_index);
+        let Some(text) = self.document.lines.get(line_index).map(|line| &line.text) else {
+            return false;
+        };
+        let tail: String = text.chars().skip(column).collect();
+        if !tail.is_empty() {
+            let len = tail.chars().count();
+            let command = EditCommand::DeleteText {
+                line: line_index,
+                column,
+                len,
+            };
+            if !self.execute(command, column) {
+                return false;
+            }
+            self.kill_ring
+                .kill(vec![(0, tail)], append.then_some((0, 0)));
+            return true;
+        }
+
+        // The trailing empty line stands for the killed line break, so a yank
+        // at column 0 puts the lines back above the caret line. The text before
+        // the caret goes too, so an earlier kill from this caret rejoins it at
+        // `column` rather than after the whole line.
+        let mut fragment = self.yank_subtrees(line_index, 1);
+        fragment.push((0, String::new()));
+        if !self.execute(EditCommand::DeleteSubtree { line: line_index }, 0) {
+            return false;
+        }
+        self.kill_ring.kill(fragment, append.then_some((0, column)));
+        true
+    }
+
+    pub fn kill_region(&mut self) -> bool {
+        let (start, end) = self.selection.normalized();
+        if start == end {
+            return false;
+        }
+        let fragment = self.document.fragment(start, end);
+        let joins_at = self.region_join(&fragment);
+        if !self.delete_selection() {
+            return false;
+        }
+        self.kill_ring.kill(fragment, joins_at);
+        true
+    }
+
+    // Emacs `kill-ring-save`: copies the selection and deactivates the mark.
+    pub fn copy_region(&mut self) -> bool {
+        let (start, end) = self.selection.normalized();
+        if start == end {
+            return false;
+        }
+        let fragment = self.document.fragment(start, end);
+        let joins_at = self.region_join(&fragment);
+        let focus = self.selection.focus;
+        self.set_caret(focus.line, focus.column);
+        self.kill_ring.kill(fragment, joins_at);
+        true
+    }
+
+    // Where an earlier kill from the mark belongs inside the region's text:
+    // before it when the region runs forward, after it when it runs backward.
+    fn region_join(&self, fragment: &[(u32, String)]) -> Option<(usize, usize)> {
+        if !self.kill_ring.appending() {
+            return None;
+        }
+        if self.selection.anchor <= self.selection.focus {
+            return Some((0, 0));
+        }
+        let row = fragment.len().checked_sub(1)?;
+        Some((row, fragment[row].1.chars().count()))
+    }
+
+    // Inserts the latest kill at the caret, replacing the selection.
+    pub fn yank(&mut self, line_index: usize) -> bool {
+        let Some(fragment) = self.kill_ring.latest() else {
+            return false;
+        };
+        let start = if self.selection.is_collapsed() {
+            self.focus_on(line_index)
+        } else {
+            self.selection.normalized().0
+        };
+        if !self.paste(line_index, fragment) {
+            return false;
+        }
+        self.kill_ring.set_last_yank(start, self.selection.focus);
+        true
+    }
+
+    // Right after a yank, swaps the yanked text for the next older kill.
+    pub fn yank_pop(&mut self) -> bool {
+        let Some((start, end)) = self.kill_ring.last_yank() else {
+            return false;
+        };
+        let Some(fragment) = self.kill_ring.rotate() else {
+            return false;
+        };
+        let commands = vec![
+            EditCommand::DeleteRange { start, end },
+            EditCommand::InsertFragment {
+                line: start.line,
+                column: start.column,
+                fragment,
+            },
+        ];
+        if !self.execute_all(commands, start.column) {
+            return false;
+        }
+        self.kill_ring.set_last_yank(start, self.selection.focus);
+        true
+    }
+
+    // Drops the mark at the caret; movements then extend the selection.
+    pub fn set_mark(&mut self, line_index: usize) {
+        let column = self.caret_column(line_index);
+        self.set_caret(line_index, column);
+        self.mark_active = true;
+    }
+
+    pub fn cancel_mark(&mut self) -> bool {
+        let focus = self.selection.focus;
+        let changed = self.mark_active || !self.selection.is_collapsed();
+        self.set_caret(focus.line, focus.column);
+        changed
+    }
+
+    // Indent shortcut handler. An explicit multi-line selection shifts
+    // exactly the selected lines; otherwise the caret line moves together
+    // with its subtree so children keep their place under it.
+    pub fn shift_indent(&mut self, line_index: usize, increase: bool) -> bool {
+        if self.selection.spans_lines() {
+            self.adjust_indent(increase)
+        } else {
+            self.adjust_subtree_indent(line_index, increase)
+        }
+    }
+
+    pub fn adjust_subtree_indent(&mut self, line_index: usize, increase: bool) -> bool {
+        let Some(line) = self.document.lines.get(line_index) else {
+            return false;
+        };
+        // Dedenting the children of a root line would detach them from it.
+        if !increase && line.indent == 0 {
+            return false;
+        }
+        let lines = self.document.subtree_range(LineKey::Index(line_index));
+        let column = self.caret_column(line_index);
+        let command = if increase {
+            EditCommand::Indent { lines }
+        } else {
+            EditCommand::Dedent { lines }
+        };
+        self.execute(command, column)
+    }
+
+    // Compresses indent jumps, e.g. after switching a document to strict
+    // hierarchy.
+    pub fn normalize_indentation(&mut self) -> bool {
+        let column = self.selection.focus.column;
+        self.execute(EditCommand::CompressIndentJumps, column)
+    }
+
+    // Shifts every line touched by the selection by one level.
+    pub fn adjust_indent(&mut self, increase: bool) -> bool {
+        let lines = self.selected_line_range();
+        self.adjust_indent_lines(lines, increase)
+    }
+
+    pub fn adjust_indent_lines(&mut self, lines: Range<usize>, increase: bool) -> bool {
+        let lines = lines.start..lines.end.min(self.document.lines.len());
+        let column = self.selection.focus.column;
+        let command = if increase {
+            EditCommand::Indent { lines }
+        } else {
+            EditCommand::Dedent { lines }
+        };
+        self.execute(command, column)
+    }
+
+    pub fn move_single_line(&mut self, line_index: usize, direction: MoveDirection) -> bool {
+        let column = self.caret_column(line_index);
+        self.execute(
+            EditCommand::MoveLine {
+                line: line_index,
+                direction,
+            },
+            column,
+        )
+    }
+
+    pub fn move_subtree(&mut self, line_index: usize, direction: MoveDirection) -> bool {
+        let column = self.caret_column(line_index);
+        self.execute(
+            EditCommand::MoveSubtree {
+                line: line_index,
+                direction,
+            },
+            column,
+        )
+    }
+
+    pub fn duplicate_subtree(&mut self, line_index: usize, direction: MoveDirection) -> bool {
+        let column = self.caret_column(line_index);
+        self.execute(
+            EditCommand::DuplicateSubtree {
+                line: line_index,
+                direction,
+            },
+            column,
+        )
+    }
+
+    pub fn insert_root_line(&mut self, line_index: usize) -> bool {
+        self.execute(EditCommand::InsertRootLine { line: line_index }, 0)
+    }
+
+    pub fn toggle_checkbox(&mut self, line_index: usize) -> bool {
+        let column = self.caret_column(line_index);
+        self.execute(EditCommand::ToggleCheckbox { line: line_index }, column)
+    }
+
+    pub fn toggle_fold(&mut self, line_index: usize) -> bool {
+        let column = self.caret_column(line_index);
+        self.execute(EditCommand::ToggleFold { line: line_index }, column)
+    }
+
+    pub fn fold_to_level(&mut self, level: u32) -> bool {
+        let column = self.selection.focus.column;
+        self.execute(EditCommand::FoldToLevel { level }, column)
+    }
+
+    pub fn toggle_fold_all(&mut self) -> bool {
+        let column = self.selection.focus.column;
+        self.execute(EditCommand::ToggleFoldAll, column)
+    }
+
+    pub fn fold_subtree(&mut self, line_index: usize) -> bool {
+        let column = self.caret_column(line_index);
+        self.execute(EditCommand::FoldSubtree { line: line_index }, column)
+    }
+
+    // Moves the caret out of a collapsed block onto the fold that hides it.
+    fn reveal_caret(&mut self) {
+        let focus = self.document.visible_ancestor(self.selection.focus.line);
+        let anchor = self.document.visible_ancestor(self.selection.anchor.line);
+        if focus != self.selection.focus.line || anchor != self.selection.anchor.line {
+            let column = self.clamp_caret_column(focus, self.selection.focus.column);
+            self.set_caret(focus, column);
+        }
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let state = self.history.undo();
+        self.restore(state)
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let state = self.history.redo();
+        self.restore(state)
+    }
+
+    pub fn switch_branch(&mut self, forward: bool) -> bool {
+        let state = self.history.switch_branch(forward);
+        self.restore(state)
+    }
+
+    fn restore(&mut self, state: Option<(Document, SelectionRange)>) -> bool {
+        let Some((document, selection)) = state else {
+            return false;
+        };
+        self.document = document;
+        self.set_selection(selection);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Replays what `adopt_dom_selection` sees before each key: the DOM
+    // reports the model selection back as `start <= end`.
+    fn adopt_dom(editor: &mut EditorState, line_index: usize) {
+        let (start, end) = editor.selection.normalized();
+        if let Some(selection) = editor.selection_from_dom(line_index, start.column, end.column) {
+            editor.set_selection(selection);
+        }
+    }
+
+    #[test]
+    fn backward_selection_survives_dom_sync() {
+        let mut editor = EditorState::from_text("hello");
+        editor.set_caret(0, 5);
+        for _ in 0..2 {
+            adopt_dom(&mut editor, 0);
+            editor.extend_left(0);
+        }
+        adopt_dom(&mut editor, 0);
+        assert_eq!(editor.selection.anchor.column, 5);
+        assert_eq!(editor.selection.focus.column, 3);
+    }
+
+    #[test]
+    fn different_dom_selection_is_adopted() {
+        let mut editor = EditorState::from_text("hello");
+        editor.set_caret(0, 5);
+        let selection = editor
+            .selection_from_dom(0, 1, 2)
+            .expect("selection differs");
+        assert_eq!(selection.anchor.column, 1);
+        assert_eq!(selection.focus.column, 2);
+    }
+
+    #[test]
+    fn mark_survives_dom_sync_while_extending_left() {
+        let mut editor = EditorState::from_text("hello");
+        editor.set_caret(0, 5);
+        editor.set_mark(0);
+        for _ in 0..2 {
+            adopt_dom(&mut editor, 0);
+            editor.extend_left(0);
+        }
+        adopt_dom(&mut editor, 0);
+        assert!(editor.mark_active);
+        assert_eq!(editor.selection.anchor.column, 5);
+        assert_eq!(editor.selection.focus.column, 3);
+    }
+
+    #[test]
+    fn repeated_kill_line_yanks_back_in_order() {
+        let mut editor = EditorState::from_text("abc\nnext");
+        editor.set_caret(0, 1);
+        assert!(editor.kill_line(0));
+        assert_eq!(editor.to_text(), "a\nnext");
+        assert!(editor.kill_line(0));
+        assert_eq!(editor.to_text(), "next");
+        assert!(editor.yank(0));
+        assert_eq!(editor.to_text(), "abc\nnext");
+    }
+
+    #[test]
+    fn backward_region_kill_joins_before_earlier_kill() {
+        let mut editor = EditorState::from_text("abcdef");
+        editor.set_caret(0, 3);
+        assert!(editor.kill_line(0));
+        editor.extend_left(0);
+        editor.extend_left(0);
+        assert!(editor.kill_region());
+        assert_eq!(editor.to_text(), "a");
+        assert!(editor.yank(0));
+        assert_eq!(editor.to_text(), "abcdef");
+    }
+
+    #[test]
+    fn yank_pop_replaces_with_older_kill() {
+        let mut editor = EditorState::from_text("one two");
+        editor.set_caret(0, 3);
+        assert!(editor.kill_line(0));
+        editor.set_caret(0, 1);
+        assert!(editor.kill_line(0));
+        assert_eq!(editor.to_text(), "o");
+        assert!(editor.yank(0));
+        assert_eq!(editor.to_text(), "one");
+        assert!(editor.yank_pop());
+        assert_eq!(editor.to_text(), "o two");
+    }
+
+    fn strict(text: &str) -> EditorState {
+        let options = crate::model::ParseOptions {
+            strict_hierarchy: true,
+            ..Default::default()
+        };
+        EditorState::new(Document::from_text_with(text, options))
+    }
+
+    // A-02: undo and redo walk back and forth over an edit.
+    #[test]
+    fn a02_undo_redo_typing() {
+        let mut editor = EditorState::from_text("");
+        editor.input(0, "ab".to_string(), 2);
+        assert!(editor.undo());
+        assert_eq!(editor.to_text(), "");
+        assert!(editor.redo());
+        assert_eq!(editor.to_text(), "ab");
+        assert_eq!(editor.selection, SelectionRange::caret(0, 2));
+    }
+
+    // E-601: a new edit after undo becomes a second branch.
+    #[test]
+    fn e601_switch_between_branches() {
+        let mut editor = EditorState::from_text("");
+        editor.input(0, "A".to_string(), 1);
+        editor.undo();
+        editor.input(0, "B".to_string(), 1);
+        assert!(editor.switch_branch(true));
+        assert_eq!(editor.to_text(), "A");
+        assert!(editor.switch_branch(true));
+        assert_eq!(editor.to_text(), "B");
+    }
+
+    // E-602: undoing a subtree move puts it back with the old caret.
+    #[test]
+    fn e602_undo_subtree_move() {
+        let mut editor = EditorState::from_text("a\nb\n\tb1");
+        editor.set_caret(1, 1);
+        assert!(editor.move_subtree(1, MoveDirection::Up));
+        assert_eq!(editor.to_text(), "b\n\tb1\na");
+        assert!(editor.undo());
+        assert_eq!(editor.to_text(), "a\nb\n\tb1");
+        assert_eq!(editor.selection, SelectionRange::caret(1, 1));
+    }
+
+    // E-603 / E-207: select all plus delete is undone with the hierarchy, and
+    // hidden lines under a fold at the end go too.
+    #[test]
+    fn e603_undo_delete_all_restores_structure() {
+        let source = "a\n\tb\nc\n\td";
+        let mut editor = EditorState::from_text(source);
+        editor.toggle_fold_all();
+        assert!(editor.select_all());
+        assert!(editor.delete_forward(0));
+        assert_eq!(editor.to_text(), "");
+        assert_eq!(editor.document.lines.len(), 1);
+        assert!(editor.undo());
+        assert_eq!(editor.to_text(), source);
+    }
+
+    #[test]
+    fn strict_mode_rolls_back_indent_jumps() {
+        let mut editor = strict("a\nb");
+        editor.set_caret(1, 0);
+        assert!(editor.adjust_subtree_indent(1, true));
+        assert_eq!(editor.to_text(), "a\n\tb");
+        let nodes = editor.history.nodes.len();
+
+        assert!(!editor.adjust_subtree_indent(1, true));
+        assert_eq!(editor.to_text(), "a\n\tb");
+        assert_eq!(editor.history.nodes.len(), nodes);
+        assert_eq!(editor.selection, SelectionRange::caret(1, 0));
+    }
+
+    #[test]
+    fn strict_rollback_undoes_every_command_of_the_step() {
+        let mut editor = strict("a\nb\nc");
+        let before = editor.document.lines.clone();
+        let commands = vec![
+            EditCommand::SetText {
+                line: 0,
+                text: "changed".to_string(),
+            },
+            EditCommand::SplitLine { line: 1, column: 1 },
+            EditCommand::Indent { lines: 2..4 },
+            EditCommand::Indent { lines: 2..3 },
+        ];
+        assert!(!editor.execute_all(commands, 0));
+        assert_eq!(editor.document.lines, before);
+    }
+
+    #[test]
+    fn normalize_indentation_compresses_jumps() {
+        let mut editor = EditorState::from_text("a\n\t\t\tb\n\t\t\t\tc");
+        assert!(editor.normalize_indentation());
+        assert_eq!(editor.to_text(), "a\n\tb\n\t\tc");
+        assert!(!editor.normalize_indentation());
+    }
+
+    // B-07: Backspace at column 0 dedents before it merges.
+    #[test]
+    fn b07_backspace_at_line_start_dedents_with_children() {
+        let mut editor = EditorState::from_text("a\n\tb\n\t\tc");
+        editor.set_caret(1, 0);
+        assert!(editor.backspace(1));
+        assert_eq!(editor.to_text(), "a\nb\n\tc");
+        assert!(editor.backspace(1));
+        assert_eq!(editor.to_text(), "ab\n\tc");
+    }
+
+    #[test]
+    fn backspace_deletes_a_whole_grapheme() {
+        let mut editor = EditorState::from_text("a👍🏽");
+        editor.set_caret(0, 3);
+        assert!(editor.backspace(0));
+        assert_eq!(editor.to_text(), "a");
+    }
+
+    #[test]
+    fn backspace_does_not_merge_into_a_hidden_line() {
+        let mut editor = EditorState::from_text("a\n\tb\nc");
+        editor.toggle_fold(0);
+        editor.set_caret(2, 0);
+        assert!(!editor.backspace(2));
+        assert_eq!(editor.to_text(), "a\n\tb\nc");
+    }
+
+    #[test]
+    fn delete_forward_at_end_of_collapsed_line_is_refused() {
+        let mut editor = EditorState::from_text("a\n\tb\nc");
+        editor.toggle_fold(0);
+        editor.set_caret(0, 1);
+        assert!(!editor.delete_forward(0));
+    }
+
+    #[test]
+    fn enter_replaces_a_multi_line_selection() {
+        let mut editor = EditorState::from_text("abc\ndef");
+        editor.set_caret(0, 1);
+        editor.extend_selection_to(1, 2);
+        assert!(editor.enter(1));
+        assert_eq!(editor.to_text(), "a\nf");
+        assert_eq!(editor.selection, SelectionRange::caret(1, 0));
+    }
+
+    #[test]
+    fn copy_and_paste_keep_relative_indentation() {
+        let mut editor = EditorState::from_text("a\n\tb\n\t\tc\nx");
+        editor.set_caret(1, 0);
+        editor.extend_selection_to(2, 1);
+        assert_eq!(editor.selected_text().as_deref(), Some("b\n\tc"));
+
+        editor.set_caret(3, 1);
+        let fragment = crate::model::parse_fragment("b\n\tc", Default::default());
+        assert!(editor.paste(3, fragment));
+        assert_eq!(editor.to_text(), "a\n\tb\n\t\tc\nxb\n\tc");
+    }
+
+    // Tab on a parent moves the children along; a multi-line selection
+    // shifts exactly the selected lines.
+    #[test]
+    fn shift_indent_carries_the_subtree() {
+        let mut editor = EditorState::from_text("a\nb\n\tc");
+        editor.set_caret(1, 0);
+        assert!(editor.shift_indent(1, true));
+        assert_eq!(editor.to_text(), "a\n\tb\n\t\tc");
+
+        editor.set_caret(1, 0);
+        editor.extend_selection_to(2, 0);
+        assert!(editor.shift_indent(2, false));
+        assert_eq!(editor.to_text(), "a\nb\n\tc");
+    }
+
+    #[test]
+    fn vertical_movement_keeps_the_goal_column() {
+        let mut editor = EditorState::from_text("abcdef\nab\nabcdef");
+        editor.set_caret(0, 5);
+        editor.move_vertically(0, MoveDirection::Down, 1, false);
+        assert_eq!(editor.selection, SelectionRange::caret(1, 2));
+        editor.move_vertically(1, MoveDirection::Down, 1, false);
+        assert_eq!(editor.selection, SelectionRange::caret(2, 5));
+    }
+
+    #[test]
+    fn vertical_movement_skips_folded_lines() {
+        let mut editor = EditorState::from_text("a\n\tb\nc");
+        editor.toggle_fold(0);
+        editor.set_caret(0, 0);
+        editor.move_vertically(0, MoveDirection::Down, 1, false);
+        assert_eq!(editor.selection.focus.line, 2);
+    }
+
+    #[test]
+    fn folding_moves_a_hidden_caret_to_the_fold() {
+        let mut editor = EditorState::from_text("a\n\tb\n\t\tc");
+        editor.set_caret(2, 1);
+        assert!(editor.fold_to_level(1));
+        assert_eq!(editor.selection.focus.line, 0);
+    }
+
+    // A-11: Alt+Left/Right jump to the parent and the first child.
+    #[test]
+    fn a11_focus_parent_and_first_child() {
+        let mut editor = EditorState::from_text("abc\n\tdefgh");
+        editor.set_caret(1, 4);
+        assert!(editor.focus_parent(1));
+        assert_eq!(editor.selection, SelectionRange::caret(0, 3));
+        assert!(editor.focus_first_child(0));
+        assert_eq!(editor.selection.focus.line, 1);
+    }
+
+    #[test]
+    fn shift_arrows_extend_across_lines() {
+        let mut editor = EditorState::from_text("ab\ncd");
+        editor.set_caret(0, 2);
+        assert!(editor.extend_right(0));
+        assert_eq!(
+            editor.selection.anchor,
+            CaretPosition { line: 0, column: 2 }
+        );
+        assert_eq!(editor.selection.focus, CaretPosition { line: 1, column: 0 });
+        assert!(editor.extend_left(1));
+        assert!(editor.selection.is_collapsed());
+    }
+
+    #[test]
+    fn select_all_spans_the_outline() {
+        let mut editor = EditorState::from_text("ab\n\tcd");
+        editor.set_caret(1, 1);
+        assert!(editor.select_all());
+        assert_eq!(
+            editor.selection.anchor,
+            CaretPosition { line: 1, column: 2 }
+        );
+        assert_eq!(editor.selection.focus, CaretPosition { line: 0, column: 0 });
+    }
+
+    #[test]
+    fn word_movement_extends_the_selection() {
+        let mut editor = EditorState::from_text("hello world");
+        editor.set_caret(0, 0);
+        editor.move_word(0, true, true);
+        assert_eq!(editor.selection.anchor.column, 0);
+        assert_eq!(editor.selection.focus.column, 5);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::model::{Document, SelectionRange};
+use std::collections::HashMap;
+
+pub type HistoryNodeId = u64;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct HistoryNode {
+    pub id: HistoryNodeId,
+    pub document: Document,
+    pub selection: SelectionRange,
+    pub selection_before: SelectionRange,
+    pub parent: Option<HistoryNodeId>,
+    pub children: Vec<HistoryNodeId>,
+    pub active_child: usize,
+    pub typing_line: Option<usize>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct History {
+    pub nodes: HashMap<HistoryNodeId, HistoryNode>,
+    pub current_id: HistoryNodeId,
+    next_id: HistoryNodeId,
+}
+
+impl History {
+    pub fn new(document: Document, selection: SelectionRange) -> Self {
+        let root = HistoryNode {
+            id: 0,
+            document,
+            selection: selection.clone(),
+            selection_before: selection,
+            parent: None,
+            children: Vec::new(),
+            active_child: 0,
+            typing_line: None,
+        };
+
+        let mut nodes = HashMap::new();
+        nodes.insert(0, root);
+
+        Self {
+            nodes,
+            current_id: 0,
+            next_id: 1,
+        }
+    }
+
+    pub fn current(&self) -> &HistoryNode {
+        &self.nodes[&self.current_id]
+    }
+
+    pub fn current_document(&self) -> &Document {
+        &self.current().document
+    }
+
+    // Adds a new node below the current one. After an undo this starts a new
+    // branch; the previous children stay reachable through `switch_branch`.
+    pub fn record(
+        &mut self,
+        document: Document,
+        selection_before: SelectionRange,
+        selection: SelectionRange,
+    ) -> bool {
+        self.push(document, selection_before, selection, None)
+    }
+
+    // Like `record`, but consecutive typing on the same line is folded into a
+    // single node so that one undo removes the whole run of input.
+    pub fn record_typing(
+        &mut self,
+        line_index: usize,
+        document: Document,
+        selection_before: SelectionRange,
+        selection: SelectionRange,
+    ) -> bool {
+        let current = self.current();
+        let can_merge = current.parent.is_some()
+            && current.children.is_empty()
+            && current.typing_line == Some(line_index);
+
+        if can_merge {
+            if current.document == document {
+                return false;
+            }
+            let node = self.nodes.get_mut(&self.current_id).expect("current node");
+            node.document = document;
+            node.selection = selection;
+            return true;
+        }
+
+        self.push(document, selection_before, selection, Some(line_index))
+    }
+
+    fn push(
+        &mut self,
+        document: Document,
+        selection_before: SelectionRange,
+        selection: SelectionRange,
+        typing_line: Option<usize>,
+    ) -> bool {
+        if self.current().document == document {
+            return false;
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.saturating_add(1);
+
+        let parent_id = self.current_id;
+        let parent = self.nodes.get_mut(&parent_id).expect("current node");
+        parent.children.push(id);
+        parent.active_child = parent.children.len() - 1;
+
+        self.nodes.insert(
+            id,
+            HistoryNode {
+                id,
+                document,
+                selection,
+                selection_before,
+                parent: Some(parent_id),
+                children: Vec::new(),
+                active_child: 0,
+                typing_line,
+            },
+        );
+        self.current_id = id;
+        true
+    }
+
+    pub fn undo(&mut self) -> Option<(Document, SelectionRange)> {
+        let current = self.current();
+        let parent_id = current.parent?;
+        let selection = current.selection_before.clone();
+
+        self.current_id = parent_id;
+        Some((self.current().document.clone(), selection))
+    }
+
+    pub fn redo(&mut self) -> Option<(Document, SelectionRange)> {
+        let current = self.current();
+        let child_id = *current.children.get(current.active_child)?;
+
+        self.current_id = child_id;
+        let node = self.current();
+        Some((node.document.clone(), node.selection.clone()))
+    }
+
+    // Moves to the neighbouring sibling of the current node, i.e. jumps to the
+    // state of another branch that was created from the same parent.
+    pub fn switch_branch(&mut self, forward: bool) -> Option<(Document, SelectionRange)> {
+        let parent_id = self.current().parent?;
+        let parent = self.nodes.get_mut(&parent_id).expect("parent node");
+        let count = parent.children.len();
+        if count < 2 {
+            return None;
+        }
+
+        let position = parent
+            .children
+            .iter()
+            .position(|id| *id == self.current_id)
+            .expect("child listed in parent");
+        let target = if forward {
+            (position + 1) % count
+        } else {
+            (position + count - 1) % count
+        };
+
+        parent.active_child = target;
+        self.current_id = parent.children[target];
+        let node = self.current();
+        Some((node.document.clone(), node.selection.clone()))
+    }
+
+    // Makes redo from `parent` follow `child`; used by branch pickers.
+    pub fn select_branch(&mut self, parent: HistoryNodeId, child: HistoryNodeId) -> bool {
+        let Some(node) = self.nodes.get_mut(&parent) else {
+            return false;
+        };
+        let Some(position) = node.children.iter().position(|id| *id == child) else {
+            return false;
+        };
+        node.active_child = position;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        Document::from_text(text)
+    }
+
+    fn text_of(state: Option<(Document, SelectionRange)>) -> Option<String> {
+        state.map(|(document, _)| document.to_text())
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_active_path() {
+        let caret = SelectionRange::caret(0, 0);
+        let mut history = History::new(document(""), caret.clone());
+        history.record(document("a"), caret.clone(), caret.clone());
+        history.record(document("ab"), caret.clone(), caret.clone());
+
+        assert_eq!(text_of(history.undo()).as_deref(), Some("a"));
+        assert_eq!(text_of(history.undo()).as_deref(), Some(""));
+        assert_eq!(history.undo(), None);
+        assert_eq!(text_of(history.redo()).as_deref(), Some("a"));
+        assert_eq!(text_of(history.redo()).as_deref(), Some("ab"));
+        assert_eq!(history.redo(), None);
+    }
+
+    // E-601: typing after an undo starts a branch; the old state stays
+    // reachable.
+    #[test]
+    fn e601_edit_after_undo_keeps_the_old_branch() {
+        let caret = SelectionRange::caret(0, 0);
+        let mut history = History::new(document(""), caret.clone());
+        history.record(document("A"), caret.clone(), caret.clone());
+        history.undo();
+        history.record(document("B"), caret.clone(), caret.clone());
+
+        assert_eq!(history.nodes[&0].children.len(), 2);
+        assert_eq!(text_of(history.switch_branch(false)).as_deref(), Some("A"));
+        assert_eq!(text_of(history.switch_branch(true)).as_deref(), Some("B"));
+
+        // Redo from the root follows whichever branch was visited last.
+        history.undo();
+        assert_eq!(text_of(history.redo()).as_deref(), Some("B"));
+        history.undo();
+        let root = history.current_id;
+        let first = history.nodes[&root].children[0];
+        assert!(history.select_branch(root, first));
+        assert_eq!(text_of(history.redo()).as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn typing_on_one_line_is_one_step() {
+        let caret = SelectionRange::caret(0, 0);
+        let mut history = History::new(document(""), caret.clone());
+        history.record_typing(0, document("a"), caret.clone(), caret.clone());
+        history.record_typing(0, document("ab"), caret.clone(), caret.clone());
+        history.record_typing(0, document("abc"), caret.clone(), caret.clone());
+
+        assert_eq!(history.nodes.len(), 2);
+        assert_eq!(text_of(history.undo()).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn typing_on_another_line_starts_a_new_step() {
+        let caret = SelectionRange::caret(0, 0);
+        let mut history = History::new(document("\n"), caret.clone());
+        history.record_typing(0, document("a\n"), caret.clone(), caret.clone());
+        history.record_typing(1, document("a\nb"), caret.clone(), caret.clone());
+
+        assert_eq!(text_of(history.undo()).as_deref(), Some("a\n"));
+    }
+
+    #[test]
+    fn unchanged_document_is_not_recorded() {
+        let caret = SelectionRange::caret(0, 0);
+        let unchanged = document("a");
+        let mut history = History::new(unchanged.clone(), caret.clone());
+        assert!(!history.record(unchanged, caret.clone(), caret));
+        assert_eq!(history.nodes.len(), 1);
+    }
+
+    #[test]
+    fn undo_restores_the_selection_before_the_edit() {
+        let before = SelectionRange::caret(0, 3);
+        let after = SelectionRange::caret(1, 0);
+        let mut history = History::new(document("abc"), before.clone());
+        history.record(document("abc\n"), before.clone(), after.clone());
+
+        let (_, selection) = history.undo().expect("undo");
+        assert_eq!(selection, before);
+        let (_, selection) = history.redo().expect("redo");
+        assert_eq!(selection, after);
+    }
+}
@@ -0,0 +1,745 @@
+use keyboard_types::{Key, Modifiers};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// A key plus the modifiers held with it, e.g. `Ctrl+Shift+Z`. Character keys
+// are stored lowercase so `Shift+A` reported as "A" still matches.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct KeyChord {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl KeyChord {
+    pub fn new(key: Key, modifiers: Modifiers) -> Self {
+        let key = match key {
+            Key::Character(text) => Key::Character(text.to_lowercase()),
+            key => key,
+        };
+        let modifiers =
+            modifiers & (Modifiers::CONTROL | Modifiers::ALT | Modifiers::SHIFT | Modifiers::META);
+        Self { key, modifiers }
+    }
+
+    // Shift turns digits and punctuation into other characters before the
+    // browser reports the key (Ctrl+Shift+1 arrives as "!"), so such a chord
+    // can never match a key event.
+    fn shift_changes_key(&self) -> bool {
+        match &self.key {
+            Key::Character(text) => {
+                self.modifiers.contains(Modifiers::SHIFT)
+                    && text != " "
+                    && text.to_lowercase() == text.to_uppercase()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = ();
+
+    // Accepts `+`-separated modifiers followed by a key name: `Ctrl+Alt+Up`,
+    // `Shift+Tab`, `Ctrl+1`, `Space`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        // `Ctrl++` binds the plus key itself.
+        let (prefix, key_name) = match text.strip_suffix("++") {
+            Some(prefix) => (prefix, "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+
+        let mut modifiers = Modifiers::empty();
+        for name in prefix.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name.trim().to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => Modifiers::CONTROL,
+                "alt" | "option" => Modifiers::ALT,
+                "shift" => Modifiers::SHIFT,
+                "meta" | "cmd" | "super" => Modifiers::META,
+                _ => return Err(()),
+            };
+        }
+
+        let key_name = key_name.trim();
+        let key = match key_name.to_ascii_lowercase().as_str() {
+            "" => return Err(()),
+            "space" => Key::Character(" ".to_string()),
+            "up" | "arrowup" => Key::ArrowUp,
+            "down" | "arrowdown" => Key::ArrowDown,
+            "left" | "arrowleft" => Key::ArrowLeft,
+            "right" | "arrowright" => Key::ArrowRight,
+            "esc" | "escape" => Key::Escape,
+            "del" | "delete" => Key::Delete,
+            "backspace" => Key::Backspace,
+            "enter" | "return" => Key::Enter,
+            "tab" => Key::Tab,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "pgup" | "pageup" => Key::PageUp,
+            "pgdn" | "pagedown" => Key::PageDown,
+            _ => Key::from_str(key_name).map_err(|_| ())?,
+        };
+        Ok(Self::new(key, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (Modifiers::CONTROL, "Ctrl+"),
+            (Modifiers::ALT, "Alt+"),
+            (Modifiers::SHIFT, "Shift+"),
+            (Modifiers::META, "Meta+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match &self.key {
+            Key::Character(text) if text == " " => f.write_str("Space"),
+            Key::Character(text) => f.write_str(&text.to_uppercase()),
+            key => write!(f, "{key}"),
+        }
+    }
+}
+
+// Named editor commands that a chord can be bound to.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum EditorAction {
+    Undo,
+    Redo,
+    NextBranch,
+    PreviousBranch,
+    ToggleFold,
+    FoldSubtree,
+    ToggleFoldAll,
+    FoldToLevel(u32),
+    DeleteSubtree,
+    NormalizeIndentation,
+    SelectAll,
+    InsertRootLine,
+    SplitLine,
+    Backspace,
+    DeleteForward,
+    Indent,
+    Dedent,
+    // Indents only when the caret is at column 0; otherwise the key types.
+    IndentAtLineStart,
+    FocusParent,
+    FocusFirstChild,
+    MoveLineUp,
+    MoveLineDown,
+    MoveSubtreeUp,
+    MoveSubtreeDown,
+    DuplicateSubtreeUp,
+    DuplicateSubtreeDown,
+    CaretLeft,
+    CaretRight,
+    CaretUp,
+    CaretDown,
+    SelectLeft,
+    SelectRight,
+    SelectUp,
+    SelectDown,
+    WordLeft,
+    WordRight,
+    SelectWordLeft,
+    SelectWordRight,
+    LineStart,
+    LineEnd,
+    SelectLineStart,
+    SelectLineEnd,
+    DocumentStart,
+    DocumentEnd,
+    SelectDocumentStart,
+    SelectDocumentEnd,
+    PageUp,
+    PageDown,
+    SelectPageUp,
+    SelectPageDown,
+    KillLine,
+    KillRegion,
+    CopyRegion,
+    Yank,
+    YankPop,
+    SetMark,
+    CancelMark,
+}
+
+const ACTION_NAMES: &[(&str, EditorAction)] = &[
+    ("undo", EditorAction::Undo),
+    ("redo", EditorAction::Redo),
+    ("next-branch", EditorAction::NextBranch),
+    ("previous-branch", EditorAction::PreviousBranch),
+    ("toggle-fold", EditorAction::ToggleFold),
+    ("fold-subtree", EditorAction::FoldSubtree),
+    ("toggle-fold-all", EditorAction::ToggleFoldAll),
+    ("delete-subtree", EditorAction::DeleteSubtree),
+    ("normalize-indentation", EditorAction::NormalizeIndentation),
+    ("select-all", EditorAction::SelectAll),
+    ("insert-root-line", EditorAction::InsertRootLine),
+    ("split-line", EditorAction::SplitLine),
+    ("backspace", EditorAction::Backspace),
+    ("delete-forward", EditorAction::DeleteForward),
+    ("indent", EditorAction::Indent),
+    ("dedent", EditorAction::Dedent),
+    ("indent-at-line-start", EditorAction::IndentAtLineStart),
+    ("focus-parent", EditorAction::FocusParent),
+    ("focus-first-child", EditorAction::FocusFirstChild),
+    ("move-line-up", EditorAction::MoveLineUp),
+    ("move-line-down", EditorAction::MoveLineDown),
+    ("move-subtree-up", EditorAction::MoveSubtreeUp),
+    ("move-subtree-down", EditorAction::MoveSubtreeDown),
+    ("duplicate-subtree-up", EditorAction::DuplicateSubtreeUp),
+    ("duplicate-subtree-down", EditorAction::DuplicateSubtreeDown),
+    ("caret-left", EditorAction::CaretLeft),
+    ("caret-right", EditorAction::CaretRight),
+    ("caret-up", EditorAction::CaretUp),
+    ("caret-down", EditorAction::CaretDown),
+    ("select-left", EditorAction::SelectLeft),
+    ("select-right", EditorAction::SelectRight),
+    ("select-up", EditorAction::SelectUp),
+    ("select-down", EditorAction::SelectDown),
+    ("word-left", EditorAction::WordLeft),
+    ("word-right", EditorAction::WordRight),
+    ("select-word-left", EditorAction::SelectWordLeft),
+    ("select-word-right", EditorAction::SelectWordRight),
+    ("line-start", EditorAction::LineStart),
+    ("line-end", EditorAction::LineEnd),
+    ("select-line-start", EditorAction::SelectLineStart),
+    ("select-line-end", EditorAction::SelectLineEnd),
+    ("document-start", EditorAction::DocumentStart),
+    ("document-end", EditorAction::DocumentEnd),
+    ("select-document-start", EditorAction::SelectDocumentStart),
+    ("select-document-end", EditorAction::SelectDocumentEnd),
+    ("page-up", EditorAction::PageUp),
+    ("page-down", EditorAction::PageDown),
+    ("select-page-up", EditorAction::SelectPageUp),
+    ("select-page-down", EditorAction::SelectPageDown),
+    ("kill-line", EditorAction::KillLine),
+    ("kill-region", EditorAction::KillRegion),
+    ("copy-region", EditorAction::CopyRegion),
+    ("yank", EditorAction::Yank),
+    ("yank-pop", EditorAction::YankPop),
+    ("set-mark", EditorAction::SetMark),
+    ("cancel-mark", EditorAction::CancelMark),
+];
+
+impl EditorAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(level) = name.strip_prefix("fold-to-level-") {
+            return level.parse().ok().map(EditorAction::FoldToLevel);
+        }
+        ACTION_NAMES
+            .iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|(_, action)| *action)
+    }
+
+    // The selecting counterpart of a caret movement, used while the mark is
+    // active. Other actions are returned unchanged.
+    pub fn extending(self) -> Self {
+        use EditorAction::*;
+
+        match self {
+            CaretLeft => SelectLeft,
+            CaretRight => SelectRight,
+            CaretUp => SelectUp,
+            CaretDown => SelectDown,
+            WordLeft => SelectWordLeft,
+            WordRight => SelectWordRight,
+            LineStart => SelectLineStart,
+            LineEnd => SelectLineEnd,
+            DocumentStart => SelectDocumentStart,
+            DocumentEnd => SelectDocumentEnd,
+            PageUp => SelectPageUp,
+            PageDown => SelectPageDown,
+            action => action,
+        }
+    }
+}
+
+impl fmt::Display for EditorAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let EditorAction::FoldToLevel(level) = self {
+            return write!(f, "fold-to-level-{level}");
+        }
+        let name = ACTION_NAMES
+            .iter()
+            .find(|(_, action)| action == self)
+            .map(|(name, _)| *name)
+            .unwrap_or("unknown");
+        f.write_str(name)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum KeymapError {
+    MalformedLine {
+        line: usize,
+    },
+    InvalidChord {
+        line: usize,
+        chord: String,
+    },
+    UnknownAction {
+        line: usize,
+        name: String,
+    },
+    // Shift held with a digit or symbol, which no key event can match.
+    ShiftedSymbol {
+        line: usize,
+        chord: String,
+    },
+    // The same chord is bound twice within one keymap source.
+    Conflict {
+        line: usize,
+        chord: KeyChord,
+        first: Option<EditorAction>,
+        second: Option<EditorAction>,
+    },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |action: &Option<EditorAction>| match action {
+            Some(action) => action.to_string(),
+            None => "unbind".to_string(),
+        };
+        match self {
+            KeymapError::MalformedLine { line } => {
+                write!(f, "line {line}: expected `<chord> = <command>`")
+            }
+            KeymapError::InvalidChord { line, chord } => {
+                write!(f, "line {line}: cannot parse key chord `{chord}`")
+            }
+            KeymapError::UnknownAction { line, name } => {
+                write!(f, "line {line}: unknown command `{name}`")
+            }
+            KeymapError::ShiftedSymbol { line, chord } => write!(
+                f,
+                "line {line}: `{chord}` never fires because Shift changes the key; \
+                 bind the shifted character instead"
+            ),
+            KeymapError::Conflict {
+                line,
+                chord,
+                first,
+                second,
+            } => write!(
+                f,
+                "line {line}: {chord} is bound to both {} and {}",
+                name(first),
+                name(second)
+            ),
+        }
+    }
+}
+
+// Maps chords to editor actions. `Keymap::default()` holds the bindings from
+// the outliner spec; user keymap files are layered on top of it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Keymap {
+    bindings: HashMap<KeyChord, EditorAction>,
+}
+
+impl Keymap {
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    pub fn action(&self, chord: &KeyChord) -> Option<EditorAction> {
+        self.bindings.get(chord).copied()
+    }
+
+    pub fn bind(&mut self, chord: KeyChord, action: EditorAction) -> Option<EditorAction> {
+        self.bindings.insert(chord, action)
+    }
+
+    pub fn unbind(&mut self, chord: &KeyChord) -> Option<EditorAction> {
+        self.bindings.remove(chord)
+    }
+
+    pub fn chords_for(&self, action: EditorAction) -> Vec<&KeyChord> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(chord, _)| chord)
+            .collect()
+    }
+
+    // Reads a keymap file and applies it on top of `self`. Each non-empty
+    // line is `<chord> = <command>`; `#` starts a comment and the command
+    // `unbind` removes an inherited binding. A chord bound twice in the same
+    // file is reported as a conflict, while a chord already bound in `self`
+    // is silently overridden by the file's binding. Shift with a digit or
+    // symbol is rejected; bind the shifted character instead (`Ctrl+!`).
+    pub fn with_config(mut self, source: &str) -> Result<Self, KeymapError> {
+        let mut seen: HashMap<KeyChord, Option<EditorAction>> = HashMap::new();
+
+        for (index, raw) in source.lines().enumerate() {
+            let line = index + 1;
+            let content = raw.split('#').next().unwrap_or("").trim();
+            if content.is_empty() {
+                continue;
+            }
+            let Some((chord_text, name)) = content.split_once('=') else {
+                return Err(KeymapError::MalformedLine { line });
+            };
+            let chord_text = chord_text.trim();
+            let chord = KeyChord::from_str(chord_text).map_err(|_| KeymapError::InvalidChord {
+                line,
+                chord: chord_text.to_string(),
+            })?;
+            if chord.shift_changes_key() {
+                return Err(KeymapError::ShiftedSymbol {
+                    line,
+                    chord: chord_text.to_string(),
+                });
+            }
+            let name = name.trim();
+            let action = match name {
+                "unbind" => None,
+                _ => Some(EditorAction::from_name(name).ok_or_else(|| {
+                    KeymapError::UnknownAction {
+                        line,
+                        name: name.to_string(),
+                    }
+                })?),
+            };
+
+            if let Some(first) = seen.insert(chord.clone(), action)
+                && first != action
+            {
+                return Err(KeymapError::Conflict {
+                    line,
+                    chord,
+                    first,
+                    second: action,
+                });
+            }
+            match action {
+                Some(action) => self.bind(chord, action),
+                None => self.unbind(&chord),
+            };
+        }
+        Ok(self)
+    }
+
+    // Emacs movement, kill ring and mark bindings layered over the defaults.
+    // Browsers keep Ctrl+N and Ctrl+W for themselves, so next-line is left to
+    // the Down arrow and kill-region moves to Ctrl+Alt+W, next to Alt+W.
+    pub fn emacs() -> Self {
+        use EditorAction::*;
+
+        let mut keymap = Self::default();
+        for (text, action) in [
+            ("Ctrl+A", LineStart),
+            ("Ctrl+E", LineEnd),
+            ("Ctrl+F", CaretRight),
+            ("Ctrl+B", CaretLeft),
+            ("Ctrl+P", CaretUp),
+            ("Ctrl+K", KillLine),
+            ("Ctrl+Alt+W", KillRegion),
+            ("Alt+W", CopyRegion),
+            ("Ctrl+Y", Yank),
+            ("Alt+Y", YankPop),
+            ("Ctrl+Space", SetMark),
+            ("Ctrl+G", CancelMark),
+            ("Ctrl+/", Undo),
+            ("Alt+Up", MoveSubtreeUp),
+            ("Alt+Down", MoveSubtreeDown),
+        ] {
+            let chord = KeyChord::from_str(text).expect("valid built-in chord");
+            keymap.bind(chord, action);
+        }
+        keymap
+    }
+
+    fn from_pairs(pairs: &[(&str, EditorAction)]) -> Self {
+        let mut keymap = Self::empty();
+        for (text, action) in pairs {
+            let chord = KeyChord::from_str(text).expect("valid built-in chord");
+            let previous = keymap.bind(chord, *action);
+            debug_assert!(previous.is_none(), "duplicate built-in chord {text}");
+        }
+        keymap
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use EditorAction::*;
+
+        let mut keymap = Self::from_pairs(&[
+            ("Ctrl+Z", Undo),
+            ("Ctrl+Shift+Z", Redo),
+            ("Ctrl+Alt+Z", NextBranch),
+            ("Ctrl+Alt+Shift+Z", PreviousBranch),
+            ("Ctrl+Enter", ToggleFold),
+            ("Ctrl+Shift+Enter", FoldSubtree),
+            ("Ctrl+Shift+A", ToggleFoldAll),
+            ("Ctrl+Shift+K", DeleteSubtree),
+            ("Ctrl+Alt+N", NormalizeIndentation),
+            ("Ctrl+A", SelectAll),
+            ("Shift+Enter", InsertRootLine),
+            ("Enter", SplitLine),
+            ("Backspace", Backspace),
+            ("Delete", DeleteForward),
+            ("Tab", Indent),
+            ("Shift+Tab", Dedent),
+            ("Ctrl+Right", Indent),
+            ("Ctrl+Left", Dedent),
+            ("Space", IndentAtLineStart),
+            ("Alt+Left", FocusParent),
+            ("Alt+Right", FocusFirstChild),
+            ("Ctrl+Up", MoveLineUp),
+            ("Ctrl+Down", MoveLineDown),
+            ("Alt+Up", MoveSubtreeUp),
+            ("Alt+Down", MoveSubtreeDown),
+            ("Alt+Shift+Up", DuplicateSubtreeUp),
+            ("Alt+Shift+Down", DuplicateSubtreeDown),
+            ("Left", CaretLeft),
+            ("Right", CaretRight),
+            ("Up", CaretUp),
+            ("Down", CaretDown),
+            ("Shift+Left", SelectLeft),
+            ("Shift+Right", SelectRight),
+            ("Shift+Up", SelectUp),
+            ("Shift+Down", SelectDown),
+            // Ctrl+Left/Right indent, so words move on Ctrl+Alt.
+            ("Ctrl+Alt+Left", WordLeft),
+            ("Ctrl+Alt+Right", WordRight),
+            ("Ctrl+Shift+Left", SelectWordLeft),
+            ("Ctrl+Shift+Right", SelectWordRight),
+            ("Ctrl+Alt+Shift+Left", SelectWordLeft),
+            ("Ctrl+Alt+Shift+Right", SelectWordRight),
+            ("Home", LineStart),
+            ("End", LineEnd),
+            ("Shift+Home", SelectLineStart),
+            ("Shift+End", SelectLineEnd),
+            ("Ctrl+Home", DocumentStart),
+            ("Ctrl+End", DocumentEnd),
+            ("Ctrl+Shift+Home", SelectDocumentStart),
+            ("Ctrl+Shift+End", SelectDocumentEnd),
+            ("PageUp", PageUp),
+            ("PageDown", PageDown),
+            ("Shift+PageUp", SelectPageUp),
+            ("Shift+PageDown", SelectPageDown),
+        ]);
+        for level in 1..=9 {
+            let chord = KeyChord::new(Key::Character(level.to_string()), Modifiers::CONTROL);
+            keymap.bind(chord, FoldToLevel(level));
+        }
+        keymap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(text: &str) -> KeyChord {
+        KeyChord::from_str(text).expect("valid chord")
+    }
+
+    #[test]
+    fn chords_parse_modifiers_and_aliases() {
+        assert_eq!(
+            chord("ctrl+alt+up"),
+            KeyChord::new(Key::ArrowUp, Modifiers::CONTROL | Modifiers::ALT)
+        );
+        assert_eq!(
+            chord("Cmd+Shift+Z"),
+            KeyChord::new(
+                Key::Character("z".to_string()),
+                Modifiers::META | Modifiers::SHIFT
+            )
+        );
+        assert_eq!(
+            chord("Space"),
+            KeyChord::new(Key::Character(" ".to_string()), Modifiers::empty())
+        );
+        assert_eq!(chord("PgDn").key, Key::PageDown);
+        assert_eq!(chord("Ctrl++").key, Key::Character("+".to_string()));
+        assert!(KeyChord::from_str("Hyper+A").is_err());
+        assert!(KeyChord::from_str("Ctrl+").is_err());
+    }
+
+    #[test]
+    fn character_keys_match_regardless_of_case() {
+        let pressed = KeyChord::new(Key::Character("Z".to_string()), Modifiers::CONTROL);
+        assert_eq!(pressed, chord("Ctrl+Z"));
+    }
+
+    #[test]
+    fn chords_display_in_canonical_form() {
+        assert_eq!(chord("shift+ctrl+space").to_string(), "Ctrl+Shift+Space");
+        assert_eq!(chord("alt+left").to_string(), "Alt+ArrowLeft");
+        assert_eq!(chord("Alt+ArrowLeft"), chord("alt+left"));
+    }
+
+    #[test]
+    fn action_names_round_trip() {
+        for (name, action) in ACTION_NAMES {
+            assert_eq!(EditorAction::from_name(name), Some(*action));
+            assert_eq!(action.to_string(), *name);
+        }
+        assert_eq!(
+            EditorAction::from_name("fold-to-level-3"),
+            Some(EditorAction::FoldToLevel(3))
+        );
+        assert_eq!(EditorAction::FoldToLevel(3).to_string(), "fold-to-level-3");
+        assert_eq!(EditorAction::from_name("fly"), None);
+    }
+
+    #[test]
+    fn default_keymap_follows_the_spec() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(&chord("Ctrl+Z")), Some(EditorAction::Undo));
+        assert_eq!(
+            keymap.action(&chord("Ctrl+Enter")),
+            Some(EditorAction::ToggleFold)
+        );
+        assert_eq!(
+            keymap.action(&chord("Ctrl+2")),
+            Some(EditorAction::FoldToLevel(2))
+        );
+        assert_eq!(
+            keymap.action(&chord("Alt+Up")),
+            Some(EditorAction::MoveSubtreeUp)
+        );
+        assert_eq!(keymap.action(&chord("Ctrl+Q")), None);
+    }
+
+    #[test]
+    fn config_rebinds_and_unbinds() {
+        let keymap = Keymap::default()
+            .with_config(
+                "# move Ctrl+arrows off the indent commands\n\
+                 Ctrl+Left = unbind\n\
+                 Ctrl+Right = unbind   # clashes with the desktop\n\
+                 \n\
+                 Alt+] = indent\n\
+                 Ctrl+Shift+L = fold-to-level-1\n",
+            )
+            .expect("valid config");
+        assert_eq!(keymap.action(&chord("Ctrl+Left")), None);
+        assert_eq!(keymap.action(&chord("Ctrl+Right")), None);
+        assert_eq!(keymap.action(&chord("Alt+]")), Some(EditorAction::Indent));
+        assert_eq!(
+            keymap.action(&chord("Ctrl+Shift+L")),
+            Some(EditorAction::FoldToLevel(1))
+        );
+        assert!(
+            keymap
+                .chords_for(EditorAction::Indent)
+                .contains(&&chord("Tab"))
+        );
+    }
+
+    #[test]
+    fn config_reports_errors_with_line_numbers() {
+        assert_eq!(
+            Keymap::empty().with_config("\nCtrl+Z undo"),
+            Err(KeymapError::MalformedLine { line: 2 })
+        );
+        assert_eq!(
+            Keymap::empty().with_config("Hyper+Z = undo"),
+            Err(KeymapError::InvalidChord {
+                line: 1,
+                chord: "Hyper+Z".to_string()
+            })
+        );
+        assert_eq!(
+            Keymap::empty().with_config("Ctrl+Shift+1 = fold-to-level-1"),
+            Err(KeymapError::ShiftedSymbol {
+                line: 1,
+                chord: "Ctrl+Shift+1".to_string()
+            })
+        );
+        assert!(Keymap::empty().with_config("Ctrl+! = undo").is_ok());
+        assert_eq!(
+            Keymap::empty().with_config("Ctrl+Z = fly"),
+            Err(KeymapError::UnknownAction {
+                line: 1,
+                name: "fly".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn config_detects_conflicting_chords() {
+        let error = Keymap::empty()
+            .with_config("Ctrl+K = delete-subtree\nctrl+k = kill-line")
+            .expect_err("conflict");
+        assert_eq!(
+            error,
+            KeymapError::Conflict {
+                line: 2,
+                chord: chord("Ctrl+K"),
+                first: Some(EditorAction::DeleteSubtree),
+                second: Some(EditorAction::KillLine),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "line 2: Ctrl+K is bound to both delete-subtree and kill-line"
+        );
+
+        // Repeating the same binding is not a conflict.
+        assert!(
+            Keymap::empty()
+                .with_config("Ctrl+K = kill-line\nCtrl+K = kill-line")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn emacs_preset_layers_over_the_defaults() {
+        let keymap = Keymap::emacs();
+        assert_eq!(
+            keymap.action(&chord("Ctrl+A")),
+            Some(EditorAction::LineStart)
+        );
+        assert_eq!(
+            keymap.action(&chord("Ctrl+K")),
+            Some(EditorAction::KillLine)
+        );
+        assert_eq!(
+            keymap.action(&chord("Ctrl+Alt+W")),
+            Some(EditorAction::KillRegion)
+        );
+        assert_eq!(keymap.action(&chord("Ctrl+W")), None);
+        assert_eq!(keymap.action(&chord("Ctrl+N")), None);
+        assert_eq!(keymap.action(&chord("Alt+Y")), Some(EditorAction::YankPop));
+        assert_eq!(
+            keymap.action(&chord("Ctrl+Space")),
+            Some(EditorAction::SetMark)
+        );
+        assert_eq!(
+            keymap.action(&chord("Alt+Down")),
+            Some(EditorAction::MoveSubtreeDown)
+        );
+        assert_eq!(
+            keymap.action(&chord("Enter")),
+            Some(EditorAction::SplitLine)
+        );
+    }
+
+    #[test]
+    fn extending_maps_movements_to_selections() {
+        assert_eq!(
+            EditorAction::CaretLeft.extending(),
+            EditorAction::SelectLeft
+        );
+        assert_eq!(
+            EditorAction::LineEnd.extending(),
+            EditorAction::SelectLineEnd
+        );
+        assert_eq!(EditorAction::Undo.extending(), EditorAction::Undo);
+    }
+}
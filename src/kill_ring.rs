@@ -0,0 +1,185 @@
+use crate::model::CaretPosition;
+
+const KILL_RING_CAPACITY: usize = 60;
+
+// Emacs-style kill ring. Entries are outline fragments with levels relative
+// to their first line, the same shape the clipboard code pastes.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct KillRing {
+    entries: Vec<Vec<(u32, String)>>,
+    // Index into `entries` of the text the last yank inserted.
+    yank_index: usize,
+    // Range covered by the last yank, so `yank-pop` can replace it.
+    last_yank: Option<(CaretPosition, CaretPosition)>,
+    // Set right after a kill; another kill then joins the same entry.
+    appending: bool,
+}
+
+impl KillRing {
+    // Stores killed text. `joins_at` is set when the previous command was a
+    // kill at the same caret: it names the row and column inside `fragment`
+    // where that earlier text was taken from, and the two are merged into one
+    // entry so a yank restores them in document order.
+    pub fn kill(&mut self, fragment: Vec<(u32, String)>, joins_at: Option<(usize, usize)>) {
+        if fragment.is_empty() {
+            return;
+        }
+        match (self.entries.last_mut(), joins_at) {
+            (Some(entry), Some((row, column))) => {
+                let earlier = std::mem::take(entry);
+                *entry = splice_fragment(fragment, row, column, earlier);
+            }
+            _ => {
+                self.entries.push(fragment);
+                if self.entries.len() > KILL_RING_CAPACITY {
+                    self.entries.remove(0);
+                }
+            }
+        }
+        self.yank_index = self.entries.len() - 1;
+        self.appending = true;
+    }
+
+    // The most recent kill, which `yank` inserts.
+    pub fn latest(&mut self) -> Option<Vec<(u32, String)>> {
+        self.yank_index = self.entries.len().checked_sub(1)?;
+        self.entries.last().cloned()
+    }
+
+    // Steps to the next older entry, wrapping around, for `yank-pop`.
+    pub fn rotate(&mut self) -> Option<Vec<(u32, String)>> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_index = self
+            .yank_index
+            .checked_sub(1)
+            .unwrap_or(self.entries.len() - 1);
+        self.entries.get(self.yank_index).cloned()
+    }
+
+    // Whether the previous command was a kill. Read it before editing, since
+    // moving the caret ends the run.
+    pub fn appending(&self) -> bool {
+        self.appending
+    }
+
+    pub fn last_yank(&self) -> Option<(CaretPosition, CaretPosition)> {
+        self.last_yank
+    }
+
+    pub fn set_last_yank(&mut self, start: CaretPosition, end: CaretPosition) {
+        self.last_yank = Some((start, end));
+    }
+
+    // Ends a run of kills or yanks; called whenever the caret moves on its own.
+    pub fn interrupt(&mut self) {
+        self.appending = false;
+        self.last_yank = None;
+    }
+}
+
+// Inserts `inner` into `outer` at `column` of line `row`. Levels in `inner` are
+// relative to its first line, which takes the level of the line it lands on.
+fn splice_fragment(
+    mut outer: Vec<(u32, String)>,
+    row: usize,
+    column: usize,
+    inner: Vec<(u32, String)>,
+) -> Vec<(u32, String)> {
+    let row = row.min(outer.len() - 1);
+    let after = outer.split_off(row + 1);
+    let (level, text) = outer.pop().expect("row inside fragment");
+    let split = text
+        .char_indices()
+        .nth(column)
+        .map_or(text.len(), |(index, _)| index);
+    let (head, tail) = text.split_at(split);
+
+    let base = inner.first().map_or(0, |(first, _)| *first);
+    let mut lines: Vec<(u32, String)> = inner
+        .into_iter()
+        .map(|(inner_level, text)| (level + inner_level.saturating_sub(base), text))
+        .collect();
+    if lines.is_empty() {
+        lines.push((level, String::new()));
+    }
+    lines[0].1.insert_str(0, head);
+    lines.last_mut().expect("non-empty").1.push_str(tail);
+
+    outer.extend(lines);
+    outer.extend(after);
+    outer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(lines: &[(u32, &str)]) -> Vec<(u32, String)> {
+        lines
+            .iter()
+            .map(|(level, text)| (*level, text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn joined_kill_after_caret_appends() {
+        let mut ring = KillRing::default();
+        ring.kill(text(&[(0, "bc")]), None);
+        ring.kill(text(&[(0, "de"), (0, "")]), Some((0, 0)));
+        assert_eq!(ring.latest(), Some(text(&[(0, "bcde"), (0, "")])));
+    }
+
+    #[test]
+    fn joined_kill_around_caret_keeps_document_order() {
+        // "abc" with the caret after "a": the tail is killed first, then the
+        // whole line, which also takes the "a" before the caret.
+        let mut ring = KillRing::default();
+        ring.kill(text(&[(0, "bc")]), None);
+        ring.kill(text(&[(0, "a"), (1, "child"), (0, "")]), Some((0, 1)));
+        assert_eq!(
+            ring.latest(),
+            Some(text(&[(0, "abc"), (1, "child"), (0, "")]))
+        );
+    }
+
+    #[test]
+    fn joined_kill_before_caret_prepends() {
+        let mut ring = KillRing::default();
+        ring.kill(text(&[(0, "c"), (1, "d")]), None);
+        ring.kill(text(&[(0, "x"), (0, "ab")]), Some((1, 2)));
+        assert_eq!(ring.latest(), Some(text(&[(0, "x"), (0, "abc"), (1, "d")])));
+    }
+
+    #[test]
+    fn separate_kills_rotate_newest_first() {
+        let mut ring = KillRing::default();
+        ring.kill(text(&[(0, "one")]), None);
+        ring.kill(text(&[(0, "two")]), None);
+        assert_eq!(ring.latest(), Some(text(&[(0, "two")])));
+        assert_eq!(ring.rotate(), Some(text(&[(0, "one")])));
+        assert_eq!(ring.rotate(), Some(text(&[(0, "two")])));
+    }
+
+    #[test]
+    fn capacity_drops_oldest_entry() {
+        let mut ring = KillRing::default();
+        for index in 0..=KILL_RING_CAPACITY {
+            ring.kill(vec![(0, index.to_string())], None);
+        }
+        assert_eq!(ring.entries.len(), KILL_RING_CAPACITY);
+        assert_eq!(ring.entries[0], vec![(0, "1".to_string())]);
+    }
+
+    #[test]
+    fn interrupt_ends_the_run() {
+        let mut ring = KillRing::default();
+        assert!(ring.latest().is_none());
+        ring.kill(text(&[(0, "x")]), None);
+        assert!(ring.appending());
+        ring.interrupt();
+        assert!(!ring.appending());
+        assert!(ring.last_yank().is_none());
+    }
+}
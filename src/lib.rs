@@ -0,0 +1,10 @@
+// Headless editing engine shared by the Dioxus front end and anything else
+// that wants to drive an outline without a browser.
+pub mod command;
+pub mod editor;
+pub mod history;
+pub mod keymap;
+pub mod kill_ring;
+pub mod lines;
+pub mod model;
+pub mod vim;
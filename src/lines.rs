@@ -0,0 +1,330 @@
+use crate::model::Line;
+use std::fmt;
+use std::ops::{Index, IndexMut, Range};
+use std::sync::Arc;
+
+const CHUNK_SIZE: usize = 64;
+
+// Persistent line storage. Lines live in fixed-size chunks behind `Arc`s, so
+// cloning a `Lines` only bumps reference counts and an edit copies just the
+// chunk it touches. History snapshots therefore share every untouched line.
+#[derive(Clone, Default)]
+pub struct Lines {
+    chunks: Arc<Vec<Arc<Vec<Line>>>>,
+    starts: Arc<Vec<usize>>,
+    len: usize,
+}
+
+impl Lines {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Line> {
+        if index >= self.len {
+            return None;
+        }
+        let (chunk, offset) = self.locate(index);
+        self.chunks[chunk].get(offset)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Line> {
+        if index >= self.len {
+            return None;
+        }
+        let (chunk, offset) = self.locate(index);
+        let chunks = Arc::make_mut(&mut self.chunks);
+        Arc::make_mut(&mut chunks[chunk]).get_mut(offset)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Line> + '_ {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    pub fn range_to_vec(&self, range: Range<usize>) -> Vec<Line> {
+        let end = range.end.min(self.len);
+        let start = range.start.min(end);
+        self.iter().skip(start).take(end - start).cloned().collect()
+    }
+
+    pub fn insert(&mut self, index: usize, line: Line) {
+        self.splice(index..index, [line]);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Line {
+        assert!(index < self.len, "line index {index} out of range");
+        self.splice(index..index + 1, [])
+            .pop()
+            .expect("removed line")
+    }
+
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        let first = self[a].clone();
+        let second = std::mem::replace(&mut self[b], first);
+        self[a] = second;
+    }
+
+    pub fn drain(&mut self, range: Range<usize>) -> std::vec::IntoIter<Line> {
+        self.splice(range, []).into_iter()
+    }
+
+    // Replaces `range` with `replacement` and returns the removed lines. Only
+    // the chunks overlapping the range are rebuilt.
+    pub fn splice<I>(&mut self, range: Range<usize>, replacement: I) -> Vec<Line>
+    where
+        I: IntoIterator<Item = Line>,
+    {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "line range {range:?} out of bounds for {} lines",
+            self.len
+        );
+
+        let chunks = Arc::make_mut(&mut self.chunks);
+
+        if chunks.is_empty() {
+            let lines: Vec<Line> = replacement.into_iter().collect();
+            *chunks = rechunk(lines);
+            self.refresh_starts();
+            return Vec::new();
+        }
+
+        let first_chunk = chunk_for(&self.starts, range.start, self.len);
+        let last_chunk = if range.end > range.start {
+            chunk_for(&self.starts, range.end - 1, self.len)
+        } else {
+            first_chunk
+        };
+        let base = self.starts[first_chunk];
+
+        let mut merged: Vec<Line> = chunks[first_chunk..=last_chunk]
+            .iter()
+            .flat_map(|chunk| chunk.iter().cloned())
+            .collect();
+        let removed: Vec<Line> = merged
+            .splice(range.start - base..range.end - base, replacement)
+            .collect();
+
+        chunks.splice(first_chunk..=last_chunk, rechunk(merged));
+        self.refresh_starts();
+        removed
+    }
+
+    pub fn shares_storage_with(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.chunks, &other.chunks)
+    }
+
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let chunk = chunk_for(&self.starts, index, self.len);
+        (chunk, index - self.starts[chunk])
+    }
+
+    fn refresh_starts(&mut self) {
+        let mut starts = Vec::with_capacity(self.chunks.len());
+        let mut total = 0usize;
+        for chunk in self.chunks.iter() {
+            starts.push(total);
+            total += chunk.len();
+        }
+        self.starts = Arc::new(starts);
+        self.len = total;
+    }
+}
+
+fn chunk_for(starts: &[usize], index: usize, len: usize) -> usize {
+    if index >= len {
+        return starts.len().saturating_sub(1);
+    }
+    starts.partition_point(|start| *start <= index) - 1
+}
+
+fn rechunk(lines: Vec<Line>) -> Vec<Arc<Vec<Line>>> {
+    if lines.is_empty() {
+        return Vec::new();
+    }
+    if lines.len() <= CHUNK_SIZE * 2 {
+        return vec![Arc::new(lines)];
+    }
+    lines
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| Arc::new(chunk.to_vec()))
+        .collect()
+}
+
+impl Index<usize> for Lines {
+    type Output = Line;
+
+    fn index(&self, index: usize) -> &Line {
+        self.get(index)
+            .unwrap_or_else(|| panic!("line index {index} out of range"))
+    }
+}
+
+impl IndexMut<usize> for Lines {
+    fn index_mut(&mut self, index: usize) -> &mut Line {
+        let len = self.len;
+        self.get_mut(index)
+            .unwrap_or_else(|| panic!("line index {index} out of range for {len} lines"))
+    }
+}
+
+impl PartialEq for Lines {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len != other.len {
+            return false;
+        }
+        if Arc::ptr_eq(&self.chunks, &other.chunks) {
+            return true;
+        }
+        if self.starts == other.starts {
+            return self
+                .chunks
+                .iter()
+                .zip(other.chunks.iter())
+                .all(|(a, b)| Arc::ptr_eq(a, b) || a == b);
+        }
+        self.iter().eq(other.iter())
+    }
+}
+
+impl fmt::Debug for Lines {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl From<Vec<Line>> for Lines {
+    fn from(lines: Vec<Line>) -> Self {
+        let mut result = Self {
+            chunks: Arc::new(
+                lines
+                    .chunks(CHUNK_SIZE)
+                    .map(|chunk| Arc::new(chunk.to_vec()))
+                    .collect(),
+            ),
+            starts: Arc::default(),
+            len: 0,
+        };
+        result.refresh_starts();
+        result
+    }
+}
+
+impl FromIterator<Line> for Lines {
+    fn from_iter<T: IntoIterator<Item = Line>>(iter: T) -> Self {
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(id: u64) -> Line {
+        Line {
+            id,
+            indent: 0,
+            text: id.to_string(),
+            collapsed: false,
+            source_indent: None,
+        }
+    }
+
+    fn numbered(count: u64) -> Lines {
+        (0..count).map(line).collect()
+    }
+
+    fn ids(lines: &Lines) -> Vec<u64> {
+        lines.iter().map(|line| line.id).collect()
+    }
+
+    #[test]
+    fn splice_across_chunk_boundary() {
+        let mut lines = numbered(200);
+        let removed = lines.splice(60..70, [line(1000), line(1001)]);
+
+        assert_eq!(
+            removed.iter().map(|l| l.id).collect::<Vec<_>>(),
+            (60..70).collect::<Vec<_>>()
+        );
+        assert_eq!(lines.len(), 192);
+        assert_eq!(lines[59].id, 59);
+        assert_eq!(lines[60].id, 1000);
+        assert_eq!(lines[61].id, 1001);
+        assert_eq!(lines[62].id, 70);
+        assert_eq!(lines[191].id, 199);
+    }
+
+    #[test]
+    fn insert_remove_and_drain_keep_order() {
+        let mut lines = numbered(5);
+        lines.insert(2, line(10));
+        assert_eq!(ids(&lines), [0, 1, 10, 2, 3, 4]);
+        assert_eq!(lines.remove(0).id, 0);
+        let drained: Vec<u64> = lines.drain(1..3).map(|l| l.id).collect();
+        assert_eq!(drained, [10, 2]);
+        assert_eq!(ids(&lines), [1, 3, 4]);
+    }
+
+    #[test]
+    fn splice_into_empty_lines() {
+        let mut lines = Lines::default();
+        lines.splice(0..0, [line(1), line(2)]);
+        assert_eq!(ids(&lines), [1, 2]);
+        assert!(lines.get(2).is_none());
+    }
+
+    #[test]
+    fn growing_chunk_is_split_again() {
+        let mut lines = numbered(10);
+        lines.splice(5..5, (100..400).map(line));
+        assert_eq!(lines.len(), 310);
+        assert!(
+            lines
+                .chunks
+                .iter()
+                .all(|chunk| chunk.len() <= CHUNK_SIZE * 2)
+        );
+        assert_eq!(lines[5].id, 100);
+        assert_eq!(lines[305].id, 5);
+    }
+
+    #[test]
+    fn edits_copy_only_the_touched_chunk() {
+        let original = numbered(CHUNK_SIZE as u64 * 4);
+        let mut edited = original.clone();
+        assert!(edited.shares_storage_with(&original));
+
+        edited[1].text.push('!');
+
+        assert!(!edited.shares_storage_with(&original));
+        assert!(!Arc::ptr_eq(&edited.chunks[0], &original.chunks[0]));
+        for chunk in 1..4 {
+            assert!(Arc::ptr_eq(&edited.chunks[chunk], &original.chunks[chunk]));
+        }
+        assert_eq!(original[1].text, "1");
+        assert_ne!(edited, original);
+    }
+
+    #[test]
+    fn equality_ignores_chunk_layout() {
+        let mut spliced = numbered(3);
+        spliced.splice(1..1, [line(9)]);
+        spliced.remove(1);
+        assert_eq!(spliced, numbered(3));
+    }
+
+    #[test]
+    fn swap_exchanges_lines() {
+        let mut lines = numbered(3);
+        lines.swap(0, 2);
+        assert_eq!(ids(&lines), [2, 1, 0]);
+    }
+}
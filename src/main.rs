@@ -1,5 +1,4 @@
 use dioxus::prelude::*;
-mod model;
 mod outliner;
 
 fn main() {
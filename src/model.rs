@@ -1,7 +1,14 @@
-use std::iter::repeat;
+use crate::lines::Lines;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub type LineId = u64;
 
+// `column` counts Unicode scalar values (`char`s) from the start of the line.
+// DOM offsets are UTF-16 code units and are converted with
+// `utf16_to_char_index` / `char_to_utf16_index` where they enter or leave the
+// model. Caret movement only ever stops on grapheme cluster boundaries.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct CaretPosition {
     pub line: usize,
@@ -31,10 +38,49 @@ impl SelectionRange {
         }
     }
 
-    #[allow(dead_code)]
     pub fn is_collapsed(&self) -> bool {
         self.anchor.line == self.focus.line && self.anchor.column == self.focus.column
     }
+
+    pub fn spans_lines(&self) -> bool {
+        self.anchor.line != self.focus.line
+    }
+
+    // Lines touched by a selection that crosses line boundaries; empty when
+    // the selection stays within one line.
+    pub fn spanned_lines(&self) -> Range<usize> {
+        if !self.spans_lines() {
+            return 0..0;
+        }
+        let (start, end) = self.normalized();
+        start.line..end.line + 1
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum IndentStyle {
+    #[default]
+    Tabs,
+    Spaces(u32),
+    FullWidthSpaces,
+}
+
+impl IndentStyle {
+    pub fn render(self, level: u32) -> String {
+        match self {
+            IndentStyle::Tabs => "\t".repeat(level as usize),
+            IndentStyle::Spaces(width) => " ".repeat((level * width.max(1)) as usize),
+            IndentStyle::FullWidthSpaces => "　".repeat(level as usize),
+        }
+    }
+}
+
+// The indentation a line had in the text it was parsed from. `to_text` writes
+// `prefix` back verbatim as long as the line is still at `level`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SourceIndent {
+    pub level: u32,
+    pub prefix: String,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -43,26 +89,86 @@ pub struct Line {
     pub indent: u32,
     pub text: String,
     pub collapsed: bool,
+    pub source_indent: Option<SourceIndent>,
+}
+
+impl Line {
+    pub fn indent_prefix(&self, style: IndentStyle) -> String {
+        match &self.source_indent {
+            Some(source) if source.level == self.indent => source.prefix.clone(),
+            _ => style.render(self.indent),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ParseOptions {
+    // Columns per indent level; `None` detects it from the leading spaces.
+    pub indent_width: Option<u32>,
+    // Columns a tab advances to; `None` makes one tab equal one level.
+    pub tab_width: Option<u32>,
+    // Whether a leading full-width space (`　`) counts as one indent level.
+    pub full_width_space_indent: bool,
+    // Opts the document into `Document::strict_hierarchy`. Indent jumps in
+    // the source are compressed while parsing.
+    pub strict_hierarchy: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: None,
+            tab_width: None,
+            full_width_space_indent: true,
+            strict_hierarchy: false,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Document {
-    pub lines: Vec<Line>,
+    pub lines: Lines,
     pub next_id: LineId,
+    pub indent_style: IndentStyle,
+    // Changes whenever line count, indents or fold state change. Values come
+    // from a process-wide counter so a restored snapshot never reuses a
+    // version that meant a different structure.
+    pub structure_version: u64,
+    // When set, no line may be more than one level deeper than the line
+    // before it, and the first line sits at level 0. Edits that would break
+    // this are rejected by `EditorState`.
+    pub strict_hierarchy: bool,
 }
 
+static STRUCTURE_VERSION: AtomicU64 = AtomicU64::new(0);
+
 impl Document {
     pub fn from_text(text: &str) -> Self {
-        let lines: Vec<Line> = text
+        Self::from_text_with(text, ParseOptions::default())
+    }
+
+    pub fn from_text_with(text: &str, options: ParseOptions) -> Self {
+        let indent_width = options
+            .indent_width
+            .unwrap_or_else(|| detect_indent_width(text, options))
+            .max(1);
+        let tab_width = options.tab_width.unwrap_or(indent_width).max(1);
+
+        let lines: Lines = text
             .split('\n')
             .enumerate()
             .map(|(index, raw_line)| {
-                let (indent, content) = split_indent(raw_line);
+                let (indent, prefix, content) =
+                    split_indent(raw_line, indent_width, tab_width, options);
                 Line {
                     id: index as LineId,
                     indent,
                     text: content.to_string(),
                     collapsed: false,
+                    source_indent: (!prefix.is_empty()).then(|| SourceIndent {
+                        level: indent,
+                        prefix: prefix.to_string(),
+                    }),
                 }
             })
             .collect();
@@ -73,25 +179,215 @@ impl Document {
             .max()
             .unwrap_or(0)
             .saturating_add(1);
+        let indent_style = detect_indent_style(&lines, indent_width);
+
+        let mut document = Self {
+            lines,
+            next_id,
+            indent_style,
+            structure_version: STRUCTURE_VERSION.fetch_add(1, Ordering::Relaxed),
+            strict_hierarchy: options.strict_hierarchy,
+        };
+        if options.strict_hierarchy {
+            document.compress_indent_jumps();
+        }
+        document
+    }
+
+    pub fn has_indent_jumps(&self) -> bool {
+        let mut allowed = 0;
+        for line in self.lines.iter() {
+            if line.indent > allowed {
+                return true;
+            }
+            allowed = line.indent + 1;
+        }
+        false
+    }
+
+    // Rewrites every indent as the line's depth in the outline, so jumps such
+    // as 1 -> 3 become 1 -> 2 while parent/child relations stay the same.
+    // Returns whether anything changed.
+    pub fn compress_indent_jumps(&mut self) -> bool {
+        let mut open: Vec<u32> = Vec::new();
+        let mut changed = false;
+        for index in 0..self.lines.len() {
+            let indent = self.lines[index].indent;
+            while open.last().is_some_and(|last| *last >= indent) {
+                open.pop();
+            }
+            let depth = open.len() as u32;
+            open.push(indent);
+            if depth != indent {
+                self.lines[index].indent = depth;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    pub fn touch_structure(&mut self) {
+        self.structure_version = STRUCTURE_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn has_children(&self, index: usize) -> bool {
+        match (self.lines.get(index), self.lines.get(index + 1)) {
+            (Some(line), Some(next)) => next.indent > line.indent,
+            _ => false,
+        }
+    }
+
+    // Sets `collapsed` on the given lines and returns the previous state of
+    // every line that actually changed.
+    pub fn set_folds(&mut self, folds: &[(usize, bool)]) -> Vec<(usize, bool)> {
+        let mut previous = Vec::new();
+        for &(index, collapsed) in folds {
+            let Some(line) = self.lines.get(index) else {
+                continue;
+            };
+            if line.collapsed != collapsed {
+                previous.push((index, line.collapsed));
+                self.lines[index].collapsed = collapsed;
+            }
+        }
+        previous
+    }
+
+    // Collapses every parent with a child at indent `level` or deeper and
+    // expands the others, so only lines with `indent < level` remain visible.
+    // Children decide rather than the parent's own indent, because after an
+    // indent jump a child can sit several levels below its parent.
+    pub fn fold_to_level(&mut self, level: u32) -> Vec<(usize, bool)> {
+        let folds: Vec<(usize, bool)> = (0..self.lines.len())
+            .filter(|index| self.has_children(*index))
+            .map(|index| {
+                let deep = self
+                    .children_of(LineKey::Index(index))
+                    .into_iter()
+                    .any(|child| self.lines[child].indent >= level);
+                (index, deep)
+            })
+            .collect();
+        self.set_folds(&folds)
+    }
+
+    // Collapses every parent, or expands everything when nothing is expanded.
+    pub fn toggle_fold_all(&mut self) -> Vec<(usize, bool)> {
+        let parents: Vec<usize> = (0..self.lines.len())
+            .filter(|index| self.has_children(*index))
+            .collect();
+        let collapse = parents.iter().any(|index| !self.lines[*index].collapsed);
+        let folds: Vec<(usize, bool)> =
+            parents.into_iter().map(|index| (index, collapse)).collect();
+        self.set_folds(&folds)
+    }
+
+    // Collapses the line and every parent below it.
+    pub fn fold_subtree(&mut self, index: usize) -> Vec<(usize, bool)> {
+        if index >= self.lines.len() {
+            return Vec::new();
+        }
+        let folds: Vec<(usize, bool)> = self
+            .subtree_range(LineKey::Index(index))
+            .filter(|line| self.has_children(*line))
+            .map(|line| (line, true))
+            .collect();
+        self.set_folds(&folds)
+    }
+
+    // The line the caret should land on when `index` may be hidden: the
+    // outermost collapsed ancestor, or `index` itself when it is visible.
+    pub fn visible_ancestor(&self, index: usize) -> usize {
+        self.ancestors(LineKey::Index(index))
+            .into_iter()
+            .rev()
+            .find(|ancestor| self.lines[*ancestor].collapsed)
+            .unwrap_or(index)
+    }
+
+    // Indices of the lines that are not hidden under a collapsed ancestor.
+    pub fn visible_lines(&self) -> Vec<usize> {
+        let mut visible = Vec::with_capacity(self.lines.len());
+        let mut hidden_below: Option<u32> = None;
+
+        for (index, line) in self.lines.iter().enumerate() {
+            if let Some(indent) = hidden_below {
+                if line.indent > indent {
+                    continue;
+                }
+                hidden_below = None;
+            }
 
-        Self { lines, next_id }
+            visible.push(index);
+            if line.collapsed {
+                hidden_below = Some(line.indent);
+            }
+        }
+
+        visible
     }
 
-    #[allow(dead_code)]
+    // Serializes the outline. Lines whose indent was not edited keep the exact
+    // prefix they were parsed with; everything else uses `indent_style`.
     pub fn to_text(&self) -> String {
         self.lines
             .iter()
-            .map(|line| {
-                format!(
-                    "{}{}",
-                    repeat('\t').take(line.indent as usize).collect::<String>(),
-                    line.text
-                )
+            .map(|line| format!("{}{}", line.indent_prefix(self.indent_style), line.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // The text between `start` and `end` as `(relative indent, text)` pairs.
+    // The first and last lines are clipped to the columns and indents are
+    // shifted so the shallowest line of the fragment sits at level 0.
+    pub fn fragment(&self, start: CaretPosition, end: CaretPosition) -> Vec<(u32, String)> {
+        let last = self.lines.len().saturating_sub(1);
+        let (start_line, end_line) = (start.line.min(last), end.line.min(last));
+        let mut fragment: Vec<(u32, String)> = (start_line..=end_line)
+            .filter_map(|index| {
+                let line = self.lines.get(index)?;
+                let from = if index == start_line { start.column } else { 0 };
+                let text: String = if index == end_line {
+                    let to = end.column.max(from);
+                    line.text.chars().take(to).skip(from).collect()
+                } else {
+                    line.text.chars().skip(from).collect()
+                };
+                Some((line.indent, text))
             })
+            .collect();
+
+        let base = fragment
+            .iter()
+            .map(|(indent, _)| *indent)
+            .min()
+            .unwrap_or(0);
+        for (indent, _) in &mut fragment {
+            *indent -= base;
+        }
+        fragment
+    }
+
+    // Serializes a fragment the way `to_text` writes whole documents.
+    pub fn fragment_to_text(&self, fragment: &[(u32, String)]) -> String {
+        fragment
+            .iter()
+            .map(|(indent, text)| format!("{}{}", self.indent_style.render(*indent), text))
             .collect::<Vec<_>>()
             .join("\n")
     }
 
+    // Drops the remembered source prefixes so that every line is written with
+    // `style` from now on.
+    pub fn normalize_indent(&mut self, style: IndentStyle) {
+        self.indent_style = style;
+        for index in 0..self.lines.len() {
+            if self.lines[index].source_indent.is_some() {
+                self.lines[index].source_indent = None;
+            }
+        }
+    }
+
     pub fn next_line_id(&mut self) -> LineId {
         let id = self.next_id;
         self.next_id = self.next_id.saturating_add(1);
@@ -99,14 +395,272 @@ impl Document {
     }
 }
 
+// Which line a tree query starts from. Indices and ids are both integers, so
+// callers name the variant instead of relying on a conversion.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LineKey {
+    Index(usize),
+    Id(LineId),
+}
+
+// Tree queries over the implicit hierarchy. A line's parent is the nearest
+// preceding line with a smaller indent, and its subtree is every following
+// line with a larger indent, so a jump from indent 1 to 3 still makes the
+// deeper line a direct child. All queries return line indices.
+impl Document {
+    pub fn index_of(&self, id: LineId) -> Option<usize> {
+        self.lines.iter().position(|line| line.id == id)
+    }
+
+    pub fn resolve(&self, key: LineKey) -> Option<usize> {
+        match key {
+            LineKey::Index(index) => (index < self.lines.len()).then_some(index),
+            LineKey::Id(id) => self.index_of(id),
+        }
+    }
+
+    pub fn subtree_range(&self, key: LineKey) -> Range<usize> {
+        match self.resolve(key) {
+            Some(index) => subtree_range(&self.lines, index),
+            None => self.lines.len()..self.lines.len(),
+        }
+    }
+
+    pub fn parent_of(&self, key: LineKey) -> Option<usize> {
+        let index = self.resolve(key)?;
+        let indent = self.lines[index].indent;
+        (0..index)
+            .rev()
+            .find(|candidate| self.lines[*candidate].indent < indent)
+    }
+
+    pub fn children_of(&self, key: LineKey) -> Vec<usize> {
+        let Some(index) = self.resolve(key) else {
+            return Vec::new();
+        };
+        let range = subtree_range(&self.lines, index);
+        let mut children = Vec::new();
+        let mut child = index + 1;
+        while child < range.end {
+            children.push(child);
+            child = subtree_range(&self.lines, child).end;
+        }
+        children
+    }
+
+    pub fn first_child(&self, key: LineKey) -> Option<usize> {
+        let index = self.resolve(key)?;
+        self.has_children(index).then_some(index + 1)
+    }
+
+    pub fn next_sibling(&self, key: LineKey) -> Option<usize> {
+        let index = self.resolve(key)?;
+        let next = subtree_range(&self.lines, index).end;
+        if next >= self.lines.len() {
+            return None;
+        }
+        let is_sibling = match self.parent_of(LineKey::Index(index)) {
+            Some(parent) => self.lines[next].indent > self.lines[parent].indent,
+            None => true,
+        };
+        is_sibling.then_some(next)
+    }
+
+    pub fn prev_sibling(&self, key: LineKey) -> Option<usize> {
+        let index = self.resolve(key)?;
+        let indent = self.lines[index].indent;
+
+        // Walk back over the heads of the subtrees that end right before
+        // `index`; the outermost one that is not an ancestor is the sibling.
+        let mut sibling = None;
+        let mut min_indent = u32::MAX;
+        for candidate in (0..index).rev() {
+            let candidate_indent = self.lines[candidate].indent;
+            if candidate_indent >= min_indent {
+                continue;
+            }
+            if candidate_indent < indent {
+                break;
+            }
+            min_indent = candidate_indent;
+            sibling = Some(candidate);
+        }
+        sibling
+    }
+
+    // Ancestors from the parent up to the root.
+    pub fn ancestors(&self, key: LineKey) -> Vec<usize> {
+        let Some(index) = self.resolve(key) else {
+            return Vec::new();
+        };
+        let mut ancestors = Vec::new();
+        let mut min_indent = self.lines[index].indent;
+        for candidate in (0..index).rev() {
+            if min_indent == 0 {
+                break;
+            }
+            if self.lines[candidate].indent < min_indent {
+                min_indent = self.lines[candidate].indent;
+                ancestors.push(candidate);
+            }
+        }
+        ancestors
+    }
+
+    // The path from the root down to the line itself, e.g. for breadcrumbs.
+    pub fn depth_path(&self, key: LineKey) -> Vec<usize> {
+        let Some(index) = self.resolve(key) else {
+            return Vec::new();
+        };
+        let mut path = self.ancestors(LineKey::Index(index));
+        path.reverse();
+        path.push(index);
+        path
+    }
+
+    // Structural depth, which differs from `indent` when indents jump.
+    pub fn depth(&self, key: LineKey) -> usize {
+        self.ancestors(key).len()
+    }
+}
+
+fn subtree_range(lines: &Lines, start_index: usize) -> Range<usize> {
+    let base_indent = lines[start_index].indent;
+    let mut end = start_index + 1;
+    while end < lines.len() && lines[end].indent > base_indent {
+        end += 1;
+    }
+    start_index..end
+}
+
 pub fn char_to_byte_index(text: &str, column: usize) -> usize {
     text.char_indices()
         .map(|(idx, _)| idx)
         .nth(column)
-        .unwrap_or_else(|| text.len())
+        .unwrap_or(text.len())
+}
+
+pub fn prev_grapheme_boundary(text: &str, column: usize) -> usize {
+    let mut boundary = 0usize;
+    let mut position = 0usize;
+    for grapheme in text.graphemes(true) {
+        if position >= column {
+            break;
+        }
+        boundary = position;
+        position += grapheme.chars().count();
+    }
+    if position < column {
+        position
+    } else {
+        boundary
+    }
+}
+
+pub fn next_grapheme_boundary(text: &str, column: usize) -> usize {
+    let mut position = 0usize;
+    for grapheme in text.graphemes(true) {
+        position += grapheme.chars().count();
+        if position > column {
+            return position;
+        }
+    }
+    position
+}
+
+// Start of the word at or before `column`, skipping whitespace. Word bounds
+// follow UAX #29, with runs of kanji, hiragana or katakana kept together.
+pub fn prev_word_boundary(text: &str, column: usize) -> usize {
+    word_segments(text)
+        .into_iter()
+        .rev()
+        .find(|segment| segment.start < column && segment.kind != WordKind::Space)
+        .map(|segment| segment.start)
+        .unwrap_or(0)
+}
+
+// End of the word at or after `column`, skipping whitespace.
+pub fn next_word_boundary(text: &str, column: usize) -> usize {
+    word_segments(text)
+        .into_iter()
+        .find(|segment| segment.end > column && segment.kind != WordKind::Space)
+        .map(|segment| segment.end)
+        .unwrap_or_else(|| text.chars().count())
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum WordKind {
+    Space,
+    Word,
+    Punctuation,
+    Kanji,
+    Hiragana,
+    Katakana,
+}
+
+struct WordSegment {
+    start: usize,
+    end: usize,
+    kind: WordKind,
+}
+
+// UAX #29 splits Japanese text into single characters, so adjacent segments of
+// the same script (and runs of punctuation) are merged back together.
+fn word_segments(text: &str) -> Vec<WordSegment> {
+    let mut segments: Vec<WordSegment> = Vec::new();
+    let mut position = 0usize;
+    for piece in text.split_word_bounds() {
+        let start = position;
+        position += piece.chars().count();
+        let previous = segments.last().map(|segment| segment.kind);
+        let kind = word_kind(piece, previous);
+
+        if let Some(last) = segments.last_mut()
+            && last.kind == kind
+            && kind != WordKind::Word
+        {
+            last.end = position;
+            continue;
+        }
+        segments.push(WordSegment {
+            start,
+            end: position,
+            kind,
+        });
+    }
+    segments
+}
+
+fn word_kind(piece: &str, previous: Option<WordKind>) -> WordKind {
+    let Some(first) = piece.chars().next() else {
+        return WordKind::Space;
+    };
+    match first {
+        _ if first.is_whitespace() => WordKind::Space,
+        // The prolonged sound mark also follows hiragana, e.g. "すごーい".
+        'ー' => match previous {
+            Some(kind @ (WordKind::Hiragana | WordKind::Katakana)) => kind,
+            _ => WordKind::Katakana,
+        },
+        '\u{3040}'..='\u{309F}' => WordKind::Hiragana,
+        '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+            WordKind::Katakana
+        }
+        '々'
+        | '〆'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}' => WordKind::Kanji,
+        '\u{20000}'..='\u{2FFFF}' => WordKind::Kanji,
+        _ if first.is_alphanumeric() => WordKind::Word,
+        _ => WordKind::Punctuation,
+    }
+}
+
+pub fn char_to_utf16_index(text: &str, column: usize) -> usize {
+    text.chars().take(column).map(char::len_utf16).sum()
 }
 
-#[allow(dead_code)]
 pub fn utf16_to_char_index(text: &str, utf16_offset: usize) -> usize {
     let mut utf16_count = 0usize;
     let mut char_index = 0usize;
@@ -123,18 +677,381 @@ pub fn utf16_to_char_index(text: &str, utf16_offset: usize) -> usize {
     char_index
 }
 
-fn split_indent(line: &str) -> (u32, &str) {
-    let mut indent = 0u32;
+// Parses pasted text into a fragment with indents relative to its shallowest
+// line, mirroring `Document::fragment`.
+pub fn parse_fragment(text: &str, options: ParseOptions) -> Vec<(u32, String)> {
+    let text = text.replace("\r\n", "\n");
+    let document = Document::from_text_with(&text, options);
+    let base = document
+        .lines
+        .iter()
+        .map(|line| line.indent)
+        .min()
+        .unwrap_or(0);
+    document
+        .lines
+        .iter()
+        .map(|line| (line.indent - base, line.text.clone()))
+        .collect()
+}
+
+fn detect_indent_style(lines: &Lines, indent_width: u32) -> IndentStyle {
+    let mut counts = [0usize; 3];
+    for line in lines.iter() {
+        let Some(source) = &line.source_indent else {
+            continue;
+        };
+        match source.prefix.chars().next() {
+            Some('\t') => counts[0] += 1,
+            Some(' ') => counts[1] += 1,
+            Some('　') => counts[2] += 1,
+            _ => {}
+        }
+    }
+
+    if counts[1] > counts[0] && counts[1] >= counts[2] {
+        IndentStyle::Spaces(indent_width)
+    } else if counts[2] > counts[0] && counts[2] > counts[1] {
+        IndentStyle::FullWidthSpaces
+    } else {
+        IndentStyle::Tabs
+    }
+}
+
+// Greatest common divisor of all space-only indents, so that files indented
+// with two or four spaces per level are recognised as such.
+fn detect_indent_width(text: &str, options: ParseOptions) -> u32 {
+    let mut width = 0u32;
+    for raw_line in text.split('\n') {
+        let prefix: Vec<char> = raw_line
+            .chars()
+            .take_while(|ch| is_indent_char(*ch, options))
+            .collect();
+        if prefix.is_empty() || prefix.iter().any(|ch| *ch != ' ') {
+            continue;
+        }
+        if prefix.len() == raw_line.chars().count() {
+            continue;
+        }
+        width = gcd(width, prefix.len() as u32);
+    }
+    width.max(1)
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn is_indent_char(ch: char, options: ParseOptions) -> bool {
+    match ch {
+        ' ' | '\t' => true,
+        '　' => options.full_width_space_indent,
+        _ => false,
+    }
+}
+
+fn split_indent(
+    line: &str,
+    indent_width: u32,
+    tab_width: u32,
+    options: ParseOptions,
+) -> (u32, &str, &str) {
+    let mut columns = 0u32;
     let mut byte_index = 0usize;
 
     for (idx, ch) in line.char_indices() {
-        if matches!(ch, ' ' | '\t' | '　') {
-            indent += 1;
-            byte_index = idx + ch.len_utf8();
-        } else {
+        if !is_indent_char(ch, options) {
             break;
         }
+        columns = match ch {
+            '\t' => (columns / tab_width + 1) * tab_width,
+            '　' => columns + indent_width,
+            _ => columns + 1,
+        };
+        byte_index = idx + ch.len_utf8();
     }
 
-    (indent, &line[byte_index..])
+    (
+        columns / indent_width,
+        &line[..byte_index],
+        &line[byte_index..],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indents(document: &Document) -> Vec<u32> {
+        document.lines.iter().map(|line| line.indent).collect()
+    }
+
+    fn texts(document: &Document, indices: &[usize]) -> Vec<String> {
+        indices
+            .iter()
+            .map(|index| document.lines[*index].text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn to_text_round_trips_mixed_indentation() {
+        let source = "root\n\tchild\n    spaces\n　full width\n\t\tdeep\n";
+        assert_eq!(Document::from_text(source).to_text(), source);
+    }
+
+    #[test]
+    fn edited_indent_is_written_with_the_document_style() {
+        let mut document = Document::from_text("a\n  b\n    c");
+        assert_eq!(document.indent_style, IndentStyle::Spaces(2));
+        document.lines[2].indent = 1;
+        assert_eq!(document.to_text(), "a\n  b\n  c");
+    }
+
+    #[test]
+    fn normalize_indent_rewrites_every_prefix() {
+        let mut document = Document::from_text("a\n    b\n\tc");
+        document.normalize_indent(IndentStyle::Tabs);
+        assert_eq!(document.to_text(), "a\n\tb\n\tc");
+    }
+
+    #[test]
+    fn indent_width_is_detected_from_spaces() {
+        assert_eq!(
+            indents(&Document::from_text("a\n  b\n    c\n  d")),
+            [0, 1, 2, 1]
+        );
+        assert_eq!(
+            indents(&Document::from_text("a\n    b\n        c")),
+            [0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn parse_options_override_detection() {
+        let options = ParseOptions {
+            indent_width: Some(1),
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            indents(&Document::from_text_with("a\n  b", options)),
+            [0, 2]
+        );
+
+        let options = ParseOptions {
+            full_width_space_indent: false,
+            ..ParseOptions::default()
+        };
+        let document = Document::from_text_with("　a", options);
+        assert_eq!(document.lines[0].indent, 0);
+        assert_eq!(document.lines[0].text, "　a");
+    }
+
+    #[test]
+    fn tabs_advance_to_the_next_tab_stop() {
+        let options = ParseOptions {
+            indent_width: Some(2),
+            tab_width: Some(4),
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            indents(&Document::from_text_with("a\n\tb\n  \tc", options)),
+            [0, 2, 2]
+        );
+    }
+
+    // B-08 / E-101: indent jumps are kept unless strict hierarchy is on.
+    #[test]
+    fn b08_indent_jumps_are_allowed_by_default() {
+        let document = Document::from_text("a\n\t\t\tb");
+        assert_eq!(indents(&document), [0, 3]);
+        assert!(document.has_indent_jumps());
+    }
+
+    #[test]
+    fn strict_parsing_compresses_jumps_into_depths() {
+        let options = ParseOptions {
+            strict_hierarchy: true,
+            ..ParseOptions::default()
+        };
+        let document = Document::from_text_with("a\n\t\t\tb\n\t\t\t\tc\n\tb2\nd", options);
+        assert_eq!(indents(&document), [0, 1, 2, 1, 0]);
+        assert!(!document.has_indent_jumps());
+    }
+
+    #[test]
+    fn collapsed_lines_hide_their_subtree() {
+        let mut document = Document::from_text("a\n\tb\n\t\tc\n\td\ne");
+        document.lines[1].collapsed = true;
+        assert_eq!(document.visible_lines(), [0, 1, 3, 4]);
+        assert_eq!(document.visible_ancestor(2), 1);
+
+        document.lines[0].collapsed = true;
+        assert_eq!(document.visible_lines(), [0, 4]);
+        assert_eq!(document.visible_ancestor(2), 0);
+        assert_eq!(document.visible_ancestor(4), 4);
+    }
+
+    #[test]
+    fn collapsed_line_without_children_hides_nothing() {
+        let mut document = Document::from_text("a\nb");
+        document.lines[0].collapsed = true;
+        assert_eq!(document.visible_lines(), [0, 1]);
+    }
+
+    // F-05: folding a subtree collapses the grandchildren as well.
+    #[test]
+    fn f05_fold_subtree_collapses_every_parent_below() {
+        let mut document = Document::from_text("a\n\tb\n\t\tc\nd\n\te");
+        let previous = document.fold_subtree(0);
+        assert_eq!(previous, [(0, false), (1, false)]);
+        assert!(!document.lines[3].collapsed);
+        assert_eq!(document.visible_lines(), [0, 3, 4]);
+    }
+
+    // F-06: Ctrl+Shift+A collapses every parent, then expands them again.
+    #[test]
+    fn f06_toggle_fold_all() {
+        let mut document = Document::from_text("a\n\tb\n\t\tc\nd\n\te");
+        document.toggle_fold_all();
+        assert_eq!(document.visible_lines(), [0, 3]);
+        document.toggle_fold_all();
+        assert_eq!(document.visible_lines(), [0, 1, 2, 3, 4]);
+    }
+
+    // F-07: Ctrl+2 shows levels 0 and 1 only.
+    #[test]
+    fn f07_fold_to_level() {
+        let mut document = Document::from_text("a\n\tb\n\t\tc\nd");
+        document.fold_to_level(2);
+        assert_eq!(texts(&document, &document.visible_lines()), ["a", "b", "d"]);
+        document.fold_to_level(1);
+        assert_eq!(texts(&document, &document.visible_lines()), ["a", "d"]);
+    }
+
+    #[test]
+    fn fold_to_level_hides_children_behind_an_indent_jump() {
+        let mut document = Document::from_text("a\n\t\t\tb\n\t\t\t\tc\nd\n\te");
+        document.fold_to_level(2);
+        assert_eq!(texts(&document, &document.visible_lines()), ["a", "d", "e"]);
+        document.fold_to_level(4);
+        assert_eq!(
+            texts(&document, &document.visible_lines()),
+            ["a", "b", "d", "e"]
+        );
+    }
+
+    #[test]
+    fn tree_queries_treat_indent_jumps_as_direct_children() {
+        // a(0) b(1) c(3) d(3) e(1) f(0)
+        let document = Document::from_text("a\n\tb\n\t\t\tc\n\t\t\td\n\te\nf");
+        let index = LineKey::Index;
+        assert_eq!(document.parent_of(index(2)), Some(1));
+        assert_eq!(document.parent_of(index(0)), None);
+        assert_eq!(document.children_of(index(1)), [2, 3]);
+        assert_eq!(document.children_of(index(0)), [1, 4]);
+        assert_eq!(document.first_child(index(1)), Some(2));
+        assert_eq!(document.next_sibling(index(2)), Some(3));
+        assert_eq!(document.next_sibling(index(3)), None);
+        assert_eq!(document.next_sibling(index(1)), Some(4));
+        assert_eq!(document.prev_sibling(index(4)), Some(1));
+        assert_eq!(document.prev_sibling(index(3)), Some(2));
+        assert_eq!(document.prev_sibling(index(5)), Some(0));
+        assert_eq!(document.ancestors(index(3)), [1, 0]);
+        assert_eq!(document.depth_path(index(3)), [0, 1, 3]);
+        assert_eq!(document.depth(index(3)), 2);
+        assert_eq!(document.subtree_range(index(1)), 1..4);
+    }
+
+    #[test]
+    fn tree_queries_accept_line_ids() {
+        let document = Document::from_text("a\n\tb\nc");
+        let id = document.lines[1].id;
+        assert_eq!(document.resolve(LineKey::Id(id)), Some(1));
+        assert_eq!(document.parent_of(LineKey::Id(id)), Some(0));
+        assert_eq!(document.resolve(LineKey::Id(LineId::MAX)), None);
+        assert_eq!(document.resolve(LineKey::Index(3)), None);
+        assert!(document.children_of(LineKey::Index(7)).is_empty());
+    }
+
+    #[test]
+    fn set_folds_reports_only_changes() {
+        let mut document = Document::from_text("a\n\tb");
+        assert_eq!(document.set_folds(&[(0, true), (1, false)]), [(0, false)]);
+        assert_eq!(document.set_folds(&[(0, true)]), []);
+    }
+
+    #[test]
+    fn fragment_clips_columns_and_relativizes_indents() {
+        let document = Document::from_text("\troot text\n\t\tchild\n\tsibling");
+        let fragment = document.fragment(
+            CaretPosition { line: 0, column: 5 },
+            CaretPosition { line: 2, column: 3 },
+        );
+        assert_eq!(
+            fragment,
+            [
+                (0, "text".to_string()),
+                (1, "child".to_string()),
+                (0, "sib".to_string()),
+            ]
+        );
+        assert_eq!(document.fragment_to_text(&fragment), "text\n\tchild\nsib");
+    }
+
+    #[test]
+    fn parse_fragment_matches_fragment_shape() {
+        let fragment = parse_fragment("  a\r\n    b\r\n  c", ParseOptions::default());
+        assert_eq!(
+            fragment,
+            [
+                (0, "a".to_string()),
+                (1, "b".to_string()),
+                (0, "c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_offsets_convert_to_chars() {
+        let text = "a😀b";
+        assert_eq!(char_to_utf16_index(text, 2), 3);
+        assert_eq!(utf16_to_char_index(text, 3), 2);
+        assert_eq!(utf16_to_char_index(text, 99), 3);
+    }
+
+    #[test]
+    fn grapheme_boundaries_skip_whole_clusters() {
+        // "e" + combining acute accent, then a flag made of two scalars.
+        let text = "e\u{301}🇯🇵x";
+        assert_eq!(next_grapheme_boundary(text, 0), 2);
+        assert_eq!(next_grapheme_boundary(text, 2), 4);
+        assert_eq!(prev_grapheme_boundary(text, 4), 2);
+        assert_eq!(prev_grapheme_boundary(text, 0), 0);
+        assert_eq!(next_grapheme_boundary(text, 5), 5);
+    }
+
+    #[test]
+    fn word_boundaries_split_japanese_by_script() {
+        let text = "私は東京タワーに行きました。";
+        let mut stops = Vec::new();
+        let mut column = 0;
+        loop {
+            let next = next_word_boundary(text, column);
+            if next == column {
+                break;
+            }
+            stops.push(next);
+            column = next;
+        }
+        // 私|は|東京|タワー|に|行|きました|。
+        assert_eq!(stops, [1, 2, 4, 7, 8, 9, 13, 14]);
+        assert_eq!(prev_word_boundary(text, 7), 4);
+    }
+
+    #[test]
+    fn word_boundaries_in_latin_text() {
+        let text = "hello, world";
+        assert_eq!(next_word_boundary(text, 0), 5);
+        assert_eq!(prev_word_boundary(text, 12), 7);
+    }
 }
@@ -1,8 +1,14 @@
-use crate::model::{Document, Line, SelectionRange, char_to_byte_index};
 use dioxus::events::FormData;
 use dioxus::prelude::*;
+use dioxus_editor::command::MoveDirection;
+use dioxus_editor::editor::EditorState;
+use dioxus_editor::keymap::{EditorAction, KeyChord, Keymap};
+use dioxus_editor::model::{
+    Document, Line, LineId, ParseOptions, SelectionRange, char_to_utf16_index, parse_fragment,
+    utf16_to_char_index,
+};
+use dioxus_editor::vim::{VimMode, VimState};
 use keyboard_types::{Key, Modifiers};
-use std::ops::Range;
 
 #[derive(Clone, PartialEq, Debug)]
 enum InlineNode {
@@ -25,70 +31,290 @@ enum LineAlignment {
     Right,
 }
 
-#[derive(Copy, Clone)]
-enum MoveDirection {
-    Up,
-    Down,
-}
-
+// Reads `selectionStart` of the input that fired the event. The DOM reports it
+// in UTF-16 code units; `None` means it is not available on this platform.
 trait CursorPositionExt {
-    fn cursor_position(&self) -> usize;
+    // Native `(selectionStart, selectionEnd)` of the event target, in UTF-16
+    // code units.
+    fn selection(&self) -> Option<(usize, usize)>;
+
+    fn cursor_position(&self) -> Option<usize> {
+        self.selection().map(|(start, _)| start)
+    }
+
+    fn target_value(&self) -> Option<String> {
+        None
+    }
 }
 
 impl CursorPositionExt for Event<FormData> {
-    fn cursor_position(&self) -> usize {
-        cursor_position_from_event(self)
+    fn selection(&self) -> Option<(usize, usize)> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            selection_of(self.data().downcast::<web_sys::Event>())
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            None
+        }
+    }
+}
+
+impl CursorPositionExt for Event<MouseData> {
+    fn selection(&self) -> Option<(usize, usize)> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            selection_of(
+                self.data()
+                    .downcast::<web_sys::MouseEvent>()
+                    .map(|event| event.as_ref()),
+            )
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            None
+        }
+    }
+}
+
+impl CursorPositionExt for Event<ClipboardData> {
+    fn selection(&self) -> Option<(usize, usize)> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            selection_of(self.data().downcast::<web_sys::Event>())
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            None
+        }
+    }
+}
+
+impl CursorPositionExt for Event<CompositionData> {
+    fn selection(&self) -> Option<(usize, usize)> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            selection_of(
+                self.data()
+                    .downcast::<web_sys::CompositionEvent>()
+                    .map(|event| event.as_ref()),
+            )
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            None
+        }
+    }
+
+    fn target_value(&self) -> Option<String> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            use wasm_bindgen::JsCast;
+
+            let data = self.data();
+            let event: &web_sys::Event = data.downcast::<web_sys::CompositionEvent>()?;
+            let target = event.target()?;
+            Some(target.dyn_ref::<web_sys::HtmlInputElement>()?.value())
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            None
+        }
+    }
+}
+
+impl CursorPositionExt for Event<FocusData> {
+    fn selection(&self) -> Option<(usize, usize)> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            selection_of(
+                self.data()
+                    .downcast::<web_sys::FocusEvent>()
+                    .map(|event| event.as_ref()),
+            )
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            None
+        }
+    }
+}
+
+impl CursorPositionExt for Event<KeyboardData> {
+    fn selection(&self) -> Option<(usize, usize)> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            selection_of(
+                self.data()
+                    .downcast::<web_sys::KeyboardEvent>()
+                    .map(|event| event.as_ref()),
+            )
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            None
+        }
     }
 }
 
 #[cfg(target_arch = "wasm32")]
-fn cursor_position_from_event(evt: &Event<FormData>) -> usize {
+fn selection_of(event: Option<&web_sys::Event>) -> Option<(usize, usize)> {
     use wasm_bindgen::JsCast;
     use web_sys::{HtmlInputElement, HtmlTextAreaElement};
 
-    let fallback = evt.value().chars().count();
+    let target = event.and_then(|event| event.target())?;
+    let (start, end) = if let Some(input) = target.dyn_ref::<HtmlInputElement>() {
+        (
+            input.selection_start().ok().flatten()?,
+            input.selection_end().ok().flatten()?,
+        )
+    } else {
+        let textarea = target.dyn_ref::<HtmlTextAreaElement>()?;
+        (
+            textarea.selection_start().ok().flatten()?,
+            textarea.selection_end().ok().flatten()?,
+        )
+    };
+    Some((start as usize, end as usize))
+}
 
-    evt.data()
-        .downcast::<web_sys::Event>()
-        .and_then(|event| event.target())
-        .and_then(|target| {
-            target
-                .dyn_ref::<HtmlInputElement>()
-                .and_then(|input| input.selection_start().ok().flatten())
-                .or_else(|| {
-                    target
-                        .dyn_ref::<HtmlTextAreaElement>()
-                        .and_then(|textarea| textarea.selection_start().ok().flatten())
-                })
-        })
-        .map(|pos| pos as usize)
-        .unwrap_or(fallback)
+// `isComposing` is false for the keydown that ends a composition in Safari,
+// which still reports the IME through `Process` / keyCode 229.
+fn is_ime_keydown(event: &KeyboardEvent) -> bool {
+    if event.data().is_composing() || event.data().key() == Key::Process {
+        return true;
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        event
+            .data()
+            .downcast::<web_sys::KeyboardEvent>()
+            .is_some_and(|event| event.key_code() == 229)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        false
+    }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn cursor_position_from_event(evt: &Event<FormData>) -> usize {
-    evt.value().chars().count()
+fn clipboard_text(event: &Event<ClipboardData>) -> Option<String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        clipboard_data_of(event)?.get_data("text/plain").ok()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = event;
+        None
+    }
+}
+
+// Puts `text` on the clipboard from inside a copy/cut handler. The caller must
+// prevent the default action for it to stick.
+fn set_clipboard_text(event: &Event<ClipboardData>, text: &str) -> bool {
+    #[cfg(target_arch = "wasm32")]
+    {
+        clipboard_data_of(event).is_some_and(|data| data.set_data("text/plain", text).is_ok())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = (event, text);
+        false
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn clipboard_data_of(event: &Event<ClipboardData>) -> Option<web_sys::DataTransfer> {
+    use wasm_bindgen::JsCast;
+
+    event
+        .data()
+        .downcast::<web_sys::Event>()?
+        .dyn_ref::<web_sys::ClipboardEvent>()?
+        .clipboard_data()
 }
 
 #[derive(Props, Clone, PartialEq)]
 pub struct OutlinerProps {
     pub initial_text: String,
+    #[props(default)]
+    pub parse_options: ParseOptions,
+    // Replaces the built-in bindings, e.g. `Keymap::default().with_config(..)`.
+    #[props(default)]
+    pub keymap: Keymap,
+    // Puts a Vim-style normal/insert mode layer in front of the keymap.
+    #[props(default)]
+    pub vim_mode: bool,
 }
 
 #[component]
 pub fn Outliner(props: OutlinerProps) -> Element {
-    let document = use_signal(|| Document::from_text(&props.initial_text));
-    let selection = use_signal(|| SelectionRange::caret(0, 0));
+    let editor = use_signal(|| {
+        EditorState::new(Document::from_text_with(
+            &props.initial_text,
+            props.parse_options,
+        ))
+    });
+
+    let keymap = use_memo(use_reactive((&props.keymap,), |(keymap,)| keymap));
+
+    let mut vim = use_signal(|| props.vim_mode.then(VimState::default));
+    use_effect(use_reactive((&props.vim_mode,), move |(enabled,)| {
+        if enabled != vim.peek().is_some() {
+            vim.set(enabled.then(VimState::default));
+        }
+    }));
+
+    // Only recompute the visible list when the outline structure changes, not
+    // on every keystroke or caret move.
+    let structure_version = use_memo(move || editor.read().document.structure_version);
+    let visible_lines = use_memo(move || {
+        structure_version();
+        editor.peek().document.visible_lines()
+    });
+
+    // Keep browser focus and the native caret in step with `selection`, so
+    // structural edits, undo and navigation land in the right input.
+    let caret_line = use_memo(move || {
+        let state = editor.read();
+        let focus = state.selection.focus;
+        state
+            .document
+            .lines
+            .get(focus.line)
+            .map(|line| (line.id, line.text.clone(), state.selection.clone()))
+    });
+    use_effect(move || {
+        if let Some((line_id, text, selection)) = caret_line() {
+            sync_dom_selection(line_id, &text, &selection);
+        }
+    });
+
+    let state = editor.read();
+    let document = &state.document;
+    let selected_lines = state.selection.spanned_lines();
+    let vim_state = vim.read();
+    let outliner_class = match vim_state.as_ref().map(|vim| vim.mode) {
+        Some(VimMode::Normal) => "outliner vim-normal",
+        _ => "outliner",
+    };
 
     rsx! {
-        div { class: "outliner",
-            for (index, line) in document.read().lines.iter().enumerate() {
+        div { class: outliner_class,
+            if let Some(vim) = vim_state.as_ref() {
+                div { class: "vim-mode", "{vim.indicator()}" }
+            }
+            for index in visible_lines.read().iter().copied() {
                 LineView {
-                    key: "{line.id}",
+                    key: "{document.lines[index].id}",
                     line_index: index,
-                    line: line.clone(),
-                    document: document.clone(),
-                    selection: selection.clone(),
+                    line: document.lines[index].clone(),
+                    has_children: document.has_children(index),
+                    selected: selected_lines.contains(&index),
+                    parse_options: props.parse_options,
+                    keymap,
+                    vim,
+                    editor,
                 }
             }
         }
@@ -99,59 +325,166 @@ pub fn Outliner(props: OutlinerProps) -> Element {
 struct LineViewProps {
     line_index: usize,
     line: Line,
-    document: Signal<Document>,
-    selection: Signal<SelectionRange>,
+    has_children: bool,
+    selected: bool,
+    parse_options: ParseOptions,
+    keymap: Memo<Keymap>,
+    vim: Signal<Option<VimState>>,
+    editor: Signal<EditorState>,
 }
 
 #[component]
 fn LineView(props: LineViewProps) -> Element {
     let line_index = props.line_index;
-    let document = props.document;
-    let mut selection = props.selection;
+    let mut editor = props.editor;
     let line = props.line;
+    let parse_options = props.parse_options;
+    let mut composing = use_signal(|| false);
 
     let fallback_text_for_focus_len = line.text.chars().count();
-    let fallback_text_for_click_len = fallback_text_for_focus_len;
+    let click_text = line.text.clone();
+
+    let mut class = String::from("outliner-line");
+    if line.collapsed {
+        class.push_str(" collapsed");
+    }
+    if props.selected {
+        class.push_str(" selected");
+    }
 
     rsx! {
         div {
-            class: "outliner-line",
+            class,
             style: format!("margin-left: {}px;", line.indent * 16),
             span { class: "line-number", "{line_index + 1}" }
-            {render_line(line_index, &line, document.clone())}
+            if props.has_children {
+                span {
+                    class: "fold-toggle",
+                    onclick: move |_| {
+                        editor.write().toggle_fold(line_index);
+                    },
+                    if line.collapsed { "▶" } else { "▼" }
+                }
+            } else {
+                span { class: "fold-toggle fold-toggle-empty" }
+            }
+            {render_line(line_index, &line, editor)}
             input {
+                id: "line-input-{line.id}",
                 class: "line-input",
                 value: line.text.clone(),
                 oninput: move |evt| {
-                    handle_input(
-                        evt.value(),
-                        evt.cursor_position(),
-                        line_index,
-                        document,
-                        selection,
-                    );
+                    // Mid-composition text stays in the DOM; writing it back
+                    // or moving the caret would commit the conversion early.
+                    if composing() {
+                        return;
+                    }
+                    let value = evt.value();
+                    let column = evt
+                        .cursor_position()
+                        .map(|offset| utf16_to_char_index(&value, offset))
+                        .unwrap_or_else(|| value.chars().count());
+                    handle_input(value, column, line_index, editor);
+                },
+                oncompositionstart: move |_| composing.set(true),
+                oncompositionend: move |evt| {
+                    composing.set(false);
+                    let Some(value) = evt.target_value() else {
+                        return;
+                    };
+                    let column = evt
+                        .cursor_position()
+                        .map(|offset| utf16_to_char_index(&value, offset))
+                        .unwrap_or_else(|| value.chars().count());
+                    handle_input(value, column, line_index, editor);
                 },
-                onfocus: move |_| {
-                    selection.set(SelectionRange::caret(
-                        line_index,
-                        fallback_text_for_focus_len,
-                    ));
+                onmousedown: move |evt| {
+                    // Shift+click on another line extends the selection. Move
+                    // the focus here first so `onfocus` keeps the anchor.
+                    let extends = evt.modifiers().contains(Modifiers::SHIFT)
+                        && editor.read().selection.focus.line != line_index;
+                    if extends {
+                        editor.write().extend_selection_to(line_index, fallback_text_for_focus_len);
+                    }
                 },
-                onclick: move |_| {
-                    selection.set(SelectionRange::caret(
-                        line_index,
-                        fallback_text_for_click_len,
-                    ));
+                onfocus: move |evt| {
+                    // Programmatic focus already placed the caret on this line.
+                    // Otherwise take the caret the browser put down, so a
+                    // click mid-line is not pushed to the end by the sync.
+                    if editor.peek().selection.focus.line != line_index {
+                        adopt_dom_selection(&evt, line_index, editor);
+                    }
+                },
+                onclick: move |evt| {
+                    let length = click_text.chars().count();
+                    let (start, end) = evt
+                        .selection()
+                        .map(|(start, end)| {
+                            (
+                                utf16_to_char_index(&click_text, start),
+                                utf16_to_char_index(&click_text, end),
+                            )
+                        })
+                        .unwrap_or((length, length));
+                    let mut state = editor.write();
+                    if evt.modifiers().contains(Modifiers::SHIFT)
+                        && state.selection.anchor.line != line_index
+                    {
+                        state.extend_selection_to(line_index, start);
+                    } else {
+                        state.set_caret(line_index, start);
+                        state.extend_selection_to(line_index, end);
+                    }
                 },
                 onkeydown: move |evt| {
-                    handle_keydown(evt, line_index, document, selection);
+                    // Keys that convert or confirm a composition belong to the IME.
+                    if composing() || is_ime_keydown(&evt) {
+                        return;
+                    }
+                    handle_keydown(evt, line_index, editor, &props.keymap.read(), props.vim);
+                },
+                // Selections inside one line are copied natively; only
+                // multi-line ones need the outline serialization.
+                oncopy: move |evt| {
+                    adopt_dom_selection(&evt, line_index, editor);
+                    let state = editor.read();
+                    if state.selection.spans_lines()
+                        && let Some(text) = state.selected_text()
+                        && set_clipboard_text(&evt, &text)
+                    {
+                        evt.prevent_default();
+                    }
+                },
+                oncut: move |evt| {
+                    adopt_dom_selection(&evt, line_index, editor);
+                    let text = {
+                        let state = editor.read();
+                        state.selection.spans_lines().then(|| state.selected_text()).flatten()
+                    };
+                    if let Some(text) = text
+                        && set_clipboard_text(&evt, &text)
+                    {
+                        evt.prevent_default();
+                        editor.write().delete_selection();
+                    }
+                },
+                onpaste: move |evt| {
+                    adopt_dom_selection(&evt, line_index, editor);
+                    let Some(text) = clipboard_text(&evt) else {
+                        return;
+                    };
+                    if text.contains('\n') || editor.read().selection.spans_lines() {
+                        evt.prevent_default();
+                        let fragment = parse_fragment(&text, parse_options);
+                        editor.write().paste(line_index, fragment);
+                    }
                 },
             }
         }
     }
 }
 
-fn render_line(line_index: usize, line: &Line, document: Signal<Document>) -> Element {
+fn render_line(line_index: usize, line: &Line, mut editor: Signal<EditorState>) -> Element {
     if let Some((level, content)) = parse_heading_line(&line.text) {
         let nodes = parse_inline_nodes(content);
         let rendered_nodes = render_inline(&nodes);
@@ -168,14 +501,15 @@ fn render_line(line_index: usize, line: &Line, document: Signal<Document>) -> El
 
     if let Some((checked, content)) = parse_checkbox_line(&line.text) {
         let nodes = parse_inline_nodes(content);
-        let toggle_handle = document.clone();
 
         return rsx! {
             div { class: "line-render",
                 input {
                     r#type: "checkbox",
                     checked: checked,
-                    onclick: move |_| toggle_checkbox(line_index, toggle_handle.clone()),
+                    onclick: move |_| {
+                        editor.write().toggle_checkbox(line_index);
+                    },
                 }
                 span { {render_inline(&nodes)} }
             }
@@ -200,10 +534,10 @@ fn parse_alignment_prefix(text: &str) -> (LineAlignment, &str) {
         ("[| ", LineAlignment::Center),
         ("[> ", LineAlignment::Right),
     ] {
-        if let Some(rest) = text.strip_prefix(marker) {
-            if let Some(end_idx) = rest.rfind(']') {
-                return (alignment, &rest[..end_idx]);
-            }
+        if let Some(rest) = text.strip_prefix(marker)
+            && let Some(end_idx) = rest.rfind(']')
+        {
+            return (alignment, &rest[..end_idx]);
         }
     }
 
@@ -423,414 +757,242 @@ fn render_node(node: &InlineNode) -> Element {
     }
 }
 
-fn toggle_checkbox(line_index: usize, mut document: Signal<Document>) {
-    if let Some(line) = document.write().lines.get_mut(line_index) {
-        if line.text.starts_with("[ ]") {
-            line.text = line.text.replacen("[ ]", "[x]", 1);
-        } else if line.text.starts_with("[x]") {
-            line.text = line.text.replacen("[x]", "[ ]", 1);
-        } else if line.text.starts_with("[X]") {
-            line.text = line.text.replacen("[X]", "[ ]", 1);
-        }
-    }
-}
-
 fn handle_input(
     new_text: String,
-    cursor_pos_bytes: usize,
+    caret_column: usize,
     line_index: usize,
-    mut document: Signal<Document>,
-    mut selection: Signal<SelectionRange>,
+    mut editor: Signal<EditorState>,
 ) {
-    let cursor_pos_bytes = cursor_pos_bytes.min(new_text.len());
-    let cursor_pos_chars = new_text[..cursor_pos_bytes].chars().count();
+    editor.write().input(line_index, new_text, caret_column);
+}
 
-    if let Some(line) = document.write().lines.get_mut(line_index) {
-        line.text = new_text;
+// The browser may have moved the native caret (mouse, IME, autofill) without
+// telling us; pick it up before acting on an event.
+fn adopt_dom_selection(
+    event: &impl CursorPositionExt,
+    line_index: usize,
+    mut editor: Signal<EditorState>,
+) {
+    let Some((start, end)) = event.selection() else {
+        return;
+    };
+    let synced = {
+        let state = editor.peek();
+        let Some(line) = state.document.lines.get(line_index) else {
+            return;
+        };
+        state.selection_from_dom(
+            line_index,
+            utf16_to_char_index(&line.text, start),
+            utf16_to_char_index(&line.text, end),
+        )
+    };
+    if let Some(selection) = synced {
+        editor.write().set_selection(selection);
     }
-
-    selection.set(SelectionRange::caret(line_index, cursor_pos_chars));
 }
 
 fn handle_keydown(
     event: KeyboardEvent,
     line_index: usize,
-    document: Signal<Document>,
-    selection: Signal<SelectionRange>,
+    mut editor: Signal<EditorState>,
+    keymap: &Keymap,
+    mut vim: Signal<Option<VimState>>,
 ) {
     let key = event.data().key();
     let modifiers = event.data().modifiers();
-    let fallback_text = document
-        .read()
-        .lines
-        .get(line_index)
-        .map(|line| line.text.clone())
-        .unwrap_or_default();
-    let caret_column = current_caret_column(line_index, selection, fallback_text.chars().count());
-
-    if key == Key::Enter && modifiers.contains(Modifiers::SHIFT) {
-        event.prevent_default();
-        insert_root_line(line_index, document, selection);
-        return;
-    }
-
-    if key == Key::Tab && modifiers.contains(Modifiers::SHIFT) {
-        event.prevent_default();
-        adjust_indent(document, selection, false);
-        return;
-    }
 
-    match key {
-        Key::Tab => {
+    // In insert mode the Vim layer only cares about Escape, so other keys
+    // skip it without touching the signals.
+    let intercept = vim
+        .peek()
+        .as_ref()
+        .is_some_and(|vim| vim.mode == VimMode::Normal || key == Key::Escape);
+    if intercept {
+        adopt_dom_selection(&event, line_index, editor);
+        let handled = vim
+            .write()
+            .as_mut()
+            .is_some_and(|vim| vim.handle_key(&key, modifiers, line_index, &mut editor.write()));
+        if handled {
             event.prevent_default();
-            adjust_indent(document, selection, true);
-        }
-        Key::ArrowLeft => {
-            if modifiers.contains(Modifiers::CONTROL) {
-                event.prevent_default();
-                adjust_indent(document, selection, false);
-            }
-        }
-        Key::ArrowRight => {
-            if modifiers.contains(Modifiers::CONTROL) {
-                event.prevent_default();
-                adjust_indent(document, selection, true);
-            }
-        }
-        Key::ArrowUp | Key::ArrowDown => {
-            let direction = if key == Key::ArrowUp {
-                MoveDirection::Up
-            } else {
-                MoveDirection::Down
-            };
-
-            if modifiers.contains(Modifiers::ALT) && modifiers.contains(Modifiers::SHIFT) {
-                event.prevent_default();
-                duplicate_subtree(line_index, document, selection, direction, caret_column);
-            } else if modifiers.contains(Modifiers::ALT) {
-                event.prevent_default();
-                move_subtree(line_index, document, selection, direction, caret_column);
-            } else if modifiers.contains(Modifiers::CONTROL) {
-                event.prevent_default();
-                move_single_line(line_index, document, selection, direction, caret_column);
-            }
-        }
-        Key::Enter => {
-            event.prevent_default();
-            handle_enter(line_index, caret_column, document, selection);
-        }
-        Key::Backspace => {
-            if caret_column == 0 {
-                event.prevent_default();
-                handle_backspace(line_index, document, selection);
-            }
-        }
-        Key::Character(ref ch) if ch == " " => {
-            if caret_column == 0 {
-                event.prevent_default();
-                adjust_indent(document, selection, true);
-            }
+            return;
         }
-        _ => {}
     }
-}
 
-fn current_caret_column(
-    line_index: usize,
-    selection: Signal<SelectionRange>,
-    fallback_len: usize,
-) -> usize {
-    let selection_state = selection.read();
-    if selection_state.focus.line == line_index {
-        selection_state.focus.column
-    } else {
-        fallback_len
+    let chord = KeyChord::new(key.clone(), modifiers);
+    let Some(action) = keymap.action(&chord) else {
+        return;
+    };
+
+    adopt_dom_selection(&event, line_index, editor);
+    if run_action(action, line_index, editor) || keeps_default_blocked(&key, modifiers) {
+        event.prevent_default();
     }
 }
 
-fn handle_enter(
-    line_index: usize,
-    caret_column: usize,
-    mut document: Signal<Document>,
-    mut selection: Signal<SelectionRange>,
-) {
-    let mut doc = document.write();
-
-    let new_id = doc.next_line_id();
-
-    if let Some(line) = doc.lines.get_mut(line_index) {
-        let split_at = char_to_byte_index(&line.text, caret_column);
-        let trailing = line.text.split_off(split_at);
-        let indent = line.indent;
-        let new_line = Line {
-            id: new_id,
-            indent,
-            text: trailing,
-            collapsed: false,
-        };
-
-        doc.lines.insert(line_index + 1, new_line);
+// Whether a bound key must not reach the browser even when its action had
+// nothing to do. Modified letters are browser shortcuts (Ctrl+W closes the
+// tab, Ctrl+Z runs the input's own undo) and Tab would move focus out of the
+// outline. Other keys only type or move the caret inside the input.
+fn keeps_default_blocked(key: &Key, modifiers: Modifiers) -> bool {
+    match key {
+        Key::Tab => true,
+        Key::Character(_) => {
+            modifiers.intersects(Modifiers::CONTROL | Modifiers::ALT | Modifiers::META)
+        }
+        _ => false,
     }
-
-    drop(doc);
-
-    selection.set(SelectionRange::caret(line_index + 1, 0));
 }
 
-fn handle_backspace(
-    line_index: usize,
-    mut document: Signal<Document>,
-    mut selection: Signal<SelectionRange>,
-) {
-    let mut new_selection = None;
-
-    {
-        let mut doc = document.write();
+// Runs a bound action. Returns whether it did anything; when it did not, the
+// key keeps its native behaviour unless `keeps_default_blocked` says otherwise.
+fn run_action(action: EditorAction, line_index: usize, mut editor: Signal<EditorState>) -> bool {
+    use EditorAction::*;
 
-        if doc.lines.is_empty() || line_index >= doc.lines.len() {
-            return;
+    let action = if editor.peek().mark_active {
+        action.extending()
+    } else {
+        action
+    };
+    let page = match action {
+        PageUp | PageDown | SelectPageUp | SelectPageDown => {
+            page_line_count(editor.peek().document.lines[line_index].id)
         }
+        _ => 0,
+    };
 
-        if let Some(line) = doc.lines.get_mut(line_index) {
-            if line.indent > 0 {
-                line.indent = line.indent.saturating_sub(1);
-                new_selection = Some(SelectionRange::caret(line_index, 0));
-            } else if line_index > 0 {
-                let current_line = doc.lines.remove(line_index);
-                let previous_line = &mut doc.lines[line_index - 1];
-                let previous_length = previous_line.text.chars().count();
-                previous_line.text.push_str(&current_line.text);
-
-                new_selection = Some(SelectionRange::caret(line_index - 1, previous_length));
+    let mut state = editor.write();
+    match action {
+        Undo => state.undo(),
+        Redo => state.redo(),
+        NextBranch => state.switch_branch(true),
+        PreviousBranch => state.switch_branch(false),
+        ToggleFold => state.toggle_fold(line_index),
+        FoldSubtree => state.fold_subtree(line_index),
+        ToggleFoldAll => state.toggle_fold_all(),
+        FoldToLevel(level) => state.fold_to_level(level),
+        DeleteSubtree => state.delete_subtree(line_index),
+        NormalizeIndentation => state.normalize_indentation(),
+        SelectAll => state.select_all(),
+        InsertRootLine => state.insert_root_line(line_index),
+        SplitLine => state.enter(line_index),
+        Backspace => state.backspace(line_index),
+        DeleteForward => state.delete_forward(line_index),
+        Indent => state.shift_indent(line_index, true),
+        Dedent => state.shift_indent(line_index, false),
+        IndentAtLineStart => {
+            if state.caret_column(line_index) != 0 {
+                return false;
             }
+            state.shift_indent(line_index, true)
         }
-    }
-
-    if let Some(selection_range) = new_selection {
-        selection.set(selection_range);
-    }
-}
-
-fn adjust_indent(
-    mut document: Signal<Document>,
-    selection: Signal<SelectionRange>,
-    increase: bool,
-) {
-    let selection_range = selection.read().clone();
-    let line_range = selected_line_range(&document.read(), &selection_range);
-
-    let mut doc = document.write();
-    for index in line_range {
-        if let Some(line) = doc.lines.get_mut(index) {
-            if increase {
-                line.indent = line.indent.saturating_add(1);
-            } else {
-                line.indent = line.indent.saturating_sub(1);
-            }
+        FocusParent => state.focus_parent(line_index),
+        FocusFirstChild => state.focus_first_child(line_index),
+        MoveLineUp => state.move_single_line(line_index, MoveDirection::Up),
+        MoveLineDown => state.move_single_line(line_index, MoveDirection::Down),
+        MoveSubtreeUp => state.move_subtree(line_index, MoveDirection::Up),
+        MoveSubtreeDown => state.move_subtree(line_index, MoveDirection::Down),
+        DuplicateSubtreeUp => state.duplicate_subtree(line_index, MoveDirection::Up),
+        DuplicateSubtreeDown => state.duplicate_subtree(line_index, MoveDirection::Down),
+        CaretLeft => state.move_caret_left(line_index),
+        CaretRight => state.move_caret_right(line_index),
+        CaretUp => state.move_vertically(line_index, MoveDirection::Up, 1, false),
+        CaretDown => state.move_vertically(line_index, MoveDirection::Down, 1, false),
+        SelectLeft => state.extend_left(line_index),
+        SelectRight => state.extend_right(line_index),
+        SelectUp => state.move_vertically(line_index, MoveDirection::Up, 1, true),
+        SelectDown => state.move_vertically(line_index, MoveDirection::Down, 1, true),
+        WordLeft => state.move_word(line_index, false, false),
+        WordRight => state.move_word(line_index, true, false),
+        SelectWordLeft => state.move_word(line_index, false, true),
+        SelectWordRight => state.move_word(line_index, true, true),
+        LineStart => state.move_to_line_boundary(line_index, false, false),
+        LineEnd => state.move_to_line_boundary(line_index, true, false),
+        SelectLineStart => state.move_to_line_boundary(line_index, false, true),
+        SelectLineEnd => state.move_to_line_boundary(line_index, true, true),
+        DocumentStart => state.move_to_document_boundary(false, false),
+        DocumentEnd => state.move_to_document_boundary(true, false),
+        SelectDocumentStart => state.move_to_document_boundary(false, true),
+        SelectDocumentEnd => state.move_to_document_boundary(true, true),
+        PageUp => state.move_vertically(line_index, MoveDirection::Up, page, false),
+        PageDown => state.move_vertically(line_index, MoveDirection::Down, page, false),
+        SelectPageUp => state.move_vertically(line_index, MoveDirection::Up, page, true),
+        SelectPageDown => state.move_vertically(line_index, MoveDirection::Down, page, true),
+        KillLine => state.kill_line(line_index),
+        KillRegion => state.kill_region(),
+        CopyRegion => state.copy_region(),
+        Yank => state.yank(line_index),
+        YankPop => state.yank_pop(),
+        SetMark => {
+            state.set_mark(line_index);
+            true
         }
+        CancelMark => state.cancel_mark(),
     }
 }
 
-fn selected_line_range(document: &Document, selection: &SelectionRange) -> Range<usize> {
-    if document.lines.is_empty() {
-        return 0..0;
-    }
-
-    let (start, end) = selection.normalized();
-    let start_line = start.line.min(document.lines.len() - 1);
-    let end_line = end.line.min(document.lines.len() - 1);
-
-    start_line..(end_line + 1)
-}
-
-fn subtree_range(lines: &[Line], start_index: usize) -> Range<usize> {
-    let base_indent = lines[start_index].indent;
-    let mut end = start_index + 1;
-    while end < lines.len() && lines[end].indent > base_indent {
-        end += 1;
-    }
-    start_index..end
-}
-
-fn clamp_caret_column(document: &Document, line_index: usize, desired_column: usize) -> usize {
-    document
-        .lines
-        .get(line_index)
-        .map(|line| desired_column.min(line.text.chars().count()))
-        .unwrap_or(0)
-}
+const DEFAULT_PAGE_LINES: usize = 20;
+// Matches the `margin-bottom` of `.outliner-line` in style.css.
+const LINE_GAP_PX: f64 = 12.0;
 
-fn move_single_line(
-    line_index: usize,
-    mut document: Signal<Document>,
-    mut selection: Signal<SelectionRange>,
-    direction: MoveDirection,
-    caret_column: usize,
-) {
-    let new_index = {
-        let mut doc = document.write();
-        if doc.lines.is_empty() || line_index >= doc.lines.len() {
-            None
-        } else {
-            match direction {
-                MoveDirection::Up => {
-                    if line_index > 0 {
-                        doc.lines.swap(line_index, line_index - 1);
-                        Some(line_index - 1)
-                    } else {
-                        None
-                    }
-                }
-                MoveDirection::Down => {
-                    if line_index + 1 < doc.lines.len() {
-                        doc.lines.swap(line_index, line_index + 1);
-                        Some(line_index + 1)
-                    } else {
-                        None
-                    }
-                }
-            }
+// How many rows fit in the viewport, measured from the row of `line_id`.
+fn page_line_count(line_id: LineId) -> usize {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let Some(window) = web_sys::window() else {
+            return DEFAULT_PAGE_LINES;
+        };
+        let viewport = window
+            .inner_height()
+            .ok()
+            .and_then(|height| height.as_f64())
+            .unwrap_or(0.0);
+        let row = window
+            .document()
+            .and_then(|document| document.get_element_by_id(&format!("line-input-{line_id}")))
+            .and_then(|input| input.parent_element())
+            .map(|row| row.get_bounding_client_rect().height())
+            .unwrap_or(0.0);
+        if viewport <= 0.0 || row <= 0.0 {
+            return DEFAULT_PAGE_LINES;
         }
-    };
-
-    if let Some(new_index) = new_index {
-        let clamped_column = clamp_caret_column(&document.read(), new_index, caret_column);
-        selection.set(SelectionRange::caret(new_index, clamped_column));
+        ((viewport / (row + LINE_GAP_PX)).floor() as usize).max(1)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = (line_id, LINE_GAP_PX);
+        DEFAULT_PAGE_LINES
     }
 }
 
-fn move_subtree(
-    line_index: usize,
-    mut document: Signal<Document>,
-    mut selection: Signal<SelectionRange>,
-    direction: MoveDirection,
-    caret_column: usize,
-) {
-    let new_index = {
-        let mut doc = document.write();
-        if line_index >= doc.lines.len() {
-            return;
-        }
-
-        let range = subtree_range(&doc.lines, line_index);
-        let base_indent = doc.lines[line_index].indent;
-
-        match direction {
-            MoveDirection::Up => {
-                let mut search = range.start as isize - 1;
-                let mut previous_start = None;
-                while search >= 0 {
-                    let idx = search as usize;
-                    let indent = doc.lines[idx].indent;
-                    if indent < base_indent {
-                        break;
-                    }
-                    if indent == base_indent {
-                        previous_start = Some(idx);
-                        break;
-                    }
-                    search -= 1;
-                }
-
-                let Some(prev_start) = previous_start else {
-                    return;
-                };
-                let block: Vec<Line> = doc.lines.drain(range.clone()).collect();
-                doc.lines.splice(prev_start..prev_start, block);
-                prev_start
-            }
-            MoveDirection::Down => {
-                let mut next_start = None;
-                for idx in range.end..doc.lines.len() {
-                    let indent = doc.lines[idx].indent;
-                    if indent < base_indent {
-                        break;
-                    }
-                    if indent == base_indent {
-                        next_start = Some(idx);
-                        break;
-                    }
-                }
-
-                let Some(next_start) = next_start else {
-                    return;
-                };
-
-                let next_range = subtree_range(&doc.lines, next_start);
-                let block: Vec<Line> = doc.lines.drain(range.clone()).collect();
-                let adjusted_next_start = next_start.saturating_sub(range.len());
-                let insert_at = adjusted_next_start + next_range.len();
-                doc.lines.splice(insert_at..insert_at, block);
-                insert_at
-            }
-        }
-    };
-
-    let clamped_column = clamp_caret_column(&document.read(), new_index, caret_column);
-    selection.set(SelectionRange::caret(new_index, clamped_column));
-}
-
-fn duplicate_subtree(
-    line_index: usize,
-    mut document: Signal<Document>,
-    mut selection: Signal<SelectionRange>,
-    direction: MoveDirection,
-    caret_column: usize,
-) {
-    let insert_at = {
-        let mut doc = document.write();
-        if line_index >= doc.lines.len() {
-            return;
-        }
-
-        let range = subtree_range(&doc.lines, line_index);
-        let new_block: Vec<Line> = doc.lines[range.clone()]
-            .to_vec()
-            .iter()
-            .map(|line| {
-                let mut new_line = line.clone();
-                new_line.id = doc.next_line_id();
-                new_line
-            })
-            .collect();
-
-        match direction {
-            MoveDirection::Up => {
-                let insert_at = range.start;
-                doc.lines.splice(insert_at..insert_at, new_block);
-                insert_at
-            }
-            MoveDirection::Down => {
-                let insert_at = range.end;
-                doc.lines.splice(insert_at..insert_at, new_block);
-                insert_at
-            }
-        }
+// Moves DOM focus to the input of the line holding the caret and mirrors the
+// selection on that line into the input's native selection.
+fn sync_dom_selection(line_id: LineId, text: &str, selection: &SelectionRange) {
+    let focus = selection.focus;
+    let (start, end) = if !selection.spans_lines() {
+        let (start, end) = selection.normalized();
+        (start.column, end.column)
+    } else {
+        (focus.column, focus.column)
     };
-
-    let clamped_column = clamp_caret_column(&document.read(), insert_at, caret_column);
-    selection.set(SelectionRange::caret(insert_at, clamped_column));
-}
-
-fn insert_root_line(
-    line_index: usize,
-    mut document: Signal<Document>,
-    mut selection: Signal<SelectionRange>,
-) {
-    let new_index = {
-        let mut doc = document.write();
-        let insert_at = line_index + 1;
-
-        let new_line = Line {
-            id: doc.next_line_id(),
-            indent: 0,
-            text: String::new(),
-            collapsed: false,
-        };
-
-        doc.lines.insert(insert_at, new_line);
-        insert_at
+    let start = char_to_utf16_index(text, start);
+    let end = char_to_utf16_index(text, end);
+    let direction = if selection.focus < selection.anchor {
+        "backward"
+    } else {
+        "forward"
     };
 
-    selection.set(SelectionRange::caret(new_index, 0));
+    document::eval(&format!(
+        r#"const input = document.getElementById("line-input-{line_id}");
+        if (input) {{
+            if (document.activeElement !== input) {{
+                input.focus();
+            }}
+            if (input.selectionStart !== {start} || input.selectionEnd !== {end}) {{
+                input.setSelectionRange({start}, {end}, "{direction}");
+            }}
+        }}"#
+    ));
 }
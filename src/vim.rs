@@ -0,0 +1,449 @@
+use crate::command::MoveDirection;
+use crate::editor::EditorState;
+use crate::model::{next_grapheme_boundary, prev_grapheme_boundary};
+use keyboard_types::{Key, Modifiers};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VimMode {
+    Normal,
+    Insert,
+}
+
+// Modal layer in front of the keymap. Normal mode keys are interpreted here;
+// insert mode only watches for Escape and leaves everything else to the
+// regular bindings.
+#[derive(Clone, PartialEq, Debug)]
+pub struct VimState {
+    pub mode: VimMode,
+    count: Option<usize>,
+    // First key of a two-key command such as `dd`, `yy` or `za`.
+    pending: Option<char>,
+    // Subtrees copied by `yy` or removed by `dd`, with relative levels.
+    register: Vec<(u32, String)>,
+}
+
+impl Default for VimState {
+    fn default() -> Self {
+        Self {
+            mode: VimMode::Normal,
+            count: None,
+            pending: None,
+            register: Vec::new(),
+        }
+    }
+}
+
+impl VimState {
+    // Text for the mode indicator, including a half-typed count or operator.
+    pub fn indicator(&self) -> String {
+        let mode = match self.mode {
+            VimMode::Normal => "-- NORMAL --",
+            VimMode::Insert => "-- INSERT --",
+        };
+        let count = self
+            .count
+            .map(|count| count.to_string())
+            .unwrap_or_default();
+        let pending = self.pending.map(String::from).unwrap_or_default();
+        format!("{mode} {count}{pending}").trim_end().to_string()
+    }
+
+    // Handles a key press on `line_index`. Returns `false` when the key
+    // should go through the keymap instead.
+    pub fn handle_key(
+        &mut self,
+        key: &Key,
+        modifiers: Modifiers,
+        line_index: usize,
+        editor: &mut EditorState,
+    ) -> bool {
+        if self.mode == VimMode::Insert {
+            if *key != Key::Escape {
+                return false;
+            }
+            self.mode = VimMode::Normal;
+            step_within_line(editor, line_index, false, 1);
+            return true;
+        }
+
+        if modifiers.intersects(Modifiers::CONTROL | Modifiers::ALT | Modifiers::META) {
+            self.pending = None;
+            if modifiers == Modifiers::CONTROL && *key == Key::Character("r".into()) {
+                for _ in 0..self.take_count() {
+                    editor.redo();
+                }
+                return true;
+            }
+            self.count = None;
+            return false;
+        }
+
+        let command = match key {
+            Key::Character(text) => text.chars().next(),
+            Key::Escape => {
+                self.count = None;
+                self.pending = None;
+                return true;
+            }
+            Key::Enter => Some('j'),
+            Key::Backspace => Some('h'),
+            Key::Delete => Some('x'),
+            _ => None,
+        };
+        let Some(command) = command else {
+            self.count = None;
+            self.pending = None;
+            return false;
+        };
+
+        if let Some(operator) = self.pending.take() {
+            let count = self.take_count();
+            self.run_operator(operator, command, count, line_index, editor);
+            return true;
+        }
+
+        match command {
+            '1'..='9' => self.push_digit(command),
+            '0' if self.count.is_some() => self.push_digit(command),
+            'd' | 'y' | '>' | '<' | 'g' | 'z' => self.pending = Some(command),
+            _ => {
+                let count = self.take_count();
+                self.run_command(command, count, line_index, editor);
+            }
+        }
+        true
+    }
+
+    fn push_digit(&mut self, digit: char) {
+        let digit = digit.to_digit(10).unwrap_or(0) as usize;
+        let count = self.count.unwrap_or(0);
+        self.count = Some(count.saturating_mul(10).saturating_add(digit));
+    }
+
+    fn take_count(&mut self) -> usize {
+        self.count.take().unwrap_or(1).max(1)
+    }
+
+    fn run_command(
+        &mut self,
+        command: char,
+        count: usize,
+        line_index: usize,
+        editor: &mut EditorState,
+    ) {
+        match command {
+            'h' => step_within_line(editor, line_index, false, count),
+            'l' => step_within_line(editor, line_index, true, count),
+            'j' => {
+                editor.move_vertically(line_index, MoveDirection::Down, count, false);
+            }
+            'k' => {
+                editor.move_vertically(line_index, MoveDirection::Up, count, false);
+            }
+            'w' | 'b' => {
+                for _ in 0..count {
+                    let line = editor.selection.focus.line;
+                    if !editor.move_word(line, command == 'w', false) {
+                        break;
+                    }
+                }
+            }
+            '0' => {
+                editor.move_to_line_boundary(line_index, false, false);
+            }
+            '$' => {
+                editor.move_to_line_boundary(line_index, true, false);
+            }
+            'G' => {
+                editor.move_to_document_boundary(true, false);
+            }
+            'x' => {
+                let start = editor.caret_column(line_index);
+                step_within_line(editor, line_index, true, count);
+                let end = editor.caret_column(line_index);
+                editor.set_caret(line_index, start);
+                editor.extend_selection_to(line_index, end);
+                editor.delete_selection();
+            }
+            'i' => self.mode = VimMode::Insert,
+            'a' => {
+                step_within_line(editor, line_index, true, 1);
+                self.mode = VimMode::Insert;
+            }
+            'I' | 'A' => {
+                editor.move_to_line_boundary(line_index, command == 'A', false);
+                self.mode = VimMode::Insert;
+            }
+            'o' | 'O' => {
+                let opened = editor.open_sibling(line_index, command == 'o');
+                if opened {
+                    self.mode = VimMode::Insert;
+                }
+            }
+            'p' | 'P' if !self.register.is_empty() => {
+                let fragment = (0..count).flat_map(|_| self.register.clone()).collect();
+                editor.put_lines(line_index, fragment, command == 'p');
+            }
+            'u' => {
+                for _ in 0..count {
+                    editor.undo();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn run_operator(
+        &mut self,
+        operator: char,
+        command: char,
+        count: usize,
+        line_index: usize,
+        editor: &mut EditorState,
+    ) {
+        match (operator, command) {
+            ('d', 'd') => {
+                self.register = editor.yank_subtrees(line_index, count);
+                editor.delete_subtrees(line_index, count);
+            }
+            ('y', 'y') => self.register = editor.yank_subtrees(line_index, count),
+            ('>', '>') | ('<', '<') => {
+                editor.set_caret(line_index, editor.caret_column(line_index));
+                editor.adjust_indent_lines(line_index..line_index + count, operator == '>');
+            }
+            ('g', 'g') => {
+                editor.move_to_document_boundary(false, false);
+            }
+            ('z', 'a') => {
+                editor.toggle_fold(line_index);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Moves the caret by graphemes without leaving the line, as `h` and `l` do.
+fn step_within_line(editor: &mut EditorState, line_index: usize, forward: bool, count: usize) {
+    let Some(line) = editor.document.lines.get(line_index) else {
+        return;
+    };
+    let mut column = editor.caret_column(line_index);
+    for _ in 0..count {
+        column = if forward {
+            next_grapheme_boundary(&line.text, column)
+        } else {
+            prev_grapheme_boundary(&line.text, column)
+        };
+    }
+    editor.set_caret(line_index, column);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SelectionRange;
+
+    // Feeds keys the way the outliner does, on the caret line. `\x1b` stands
+    // for Escape.
+    fn press(vim: &mut VimState, editor: &mut EditorState, keys: &str) {
+        for key in keys.chars() {
+            let key = match key {
+                '\x1b' => Key::Escape,
+                key => Key::Character(key.to_string()),
+            };
+            let line = editor.selection.focus.line;
+            vim.handle_key(&key, Modifiers::empty(), line, editor);
+        }
+    }
+
+    fn setup(text: &str) -> (VimState, EditorState) {
+        (VimState::default(), EditorState::from_text(text))
+    }
+
+    #[test]
+    fn motions_take_counts() {
+        let (mut vim, mut editor) = setup("abcdef\nb\nc\nd");
+        press(&mut vim, &mut editor, "3l");
+        assert_eq!(editor.selection, SelectionRange::caret(0, 3));
+        press(&mut vim, &mut editor, "h2j");
+        assert_eq!(editor.selection.focus.line, 2);
+        press(&mut vim, &mut editor, "G");
+        assert_eq!(editor.selection.focus.line, 3);
+        press(&mut vim, &mut editor, "gg$");
+        assert_eq!(editor.selection, SelectionRange::caret(0, 6));
+        press(&mut vim, &mut editor, "0");
+        assert_eq!(editor.selection, SelectionRange::caret(0, 0));
+    }
+
+    #[test]
+    fn h_and_l_stay_on_the_line() {
+        let (mut vim, mut editor) = setup("ab\ncd");
+        editor.set_caret(1, 0);
+        press(&mut vim, &mut editor, "h");
+        assert_eq!(editor.selection, SelectionRange::caret(1, 0));
+        press(&mut vim, &mut editor, "9l");
+        assert_eq!(editor.selection, SelectionRange::caret(1, 2));
+    }
+
+    #[test]
+    fn word_motions() {
+        let (mut vim, mut editor) = setup("one two three");
+        press(&mut vim, &mut editor, "2w");
+        assert_eq!(editor.selection.focus.column, 7);
+        press(&mut vim, &mut editor, "b");
+        assert_eq!(editor.selection.focus.column, 4);
+    }
+
+    #[test]
+    fn dd_deletes_subtrees_and_p_puts_them_back() {
+        let (mut vim, mut editor) = setup("a\n\tb\nc\n\td\ne");
+        press(&mut vim, &mut editor, "2dd");
+        assert_eq!(editor.to_text(), "e");
+        press(&mut vim, &mut editor, "P");
+        assert_eq!(editor.to_text(), "a\n\tb\nc\n\td\ne");
+        // The whole count was one step.
+        press(&mut vim, &mut editor, "uu");
+        assert_eq!(editor.to_text(), "a\n\tb\nc\n\td\ne");
+    }
+
+    #[test]
+    fn dd_count_stops_at_the_last_sibling() {
+        let (mut vim, mut editor) = setup("a\n\tb\n\tc\nd");
+        editor.set_caret(1, 0);
+        press(&mut vim, &mut editor, "5dd");
+        assert_eq!(editor.to_text(), "a\nd");
+    }
+
+    #[test]
+    fn yy_and_p_copy_a_subtree_below_the_current_one() {
+        let (mut vim, mut editor) = setup("a\n\tb\nc\n\td\ne");
+        press(&mut vim, &mut editor, "yyjjp");
+        assert_eq!(editor.to_text(), "a\n\tb\nc\n\td\na\n\tb\ne");
+        assert_eq!(editor.selection.focus.line, 4);
+        press(&mut vim, &mut editor, "u");
+        assert_eq!(editor.to_text(), "a\n\tb\nc\n\td\ne");
+    }
+
+    #[test]
+    fn put_takes_the_indent_of_the_caret_line() {
+        let (mut vim, mut editor) = setup("a\nb\n\tc");
+        press(&mut vim, &mut editor, "yyjjp");
+        assert_eq!(editor.to_text(), "a\nb\n\tc\n\ta");
+    }
+
+    #[test]
+    fn indent_operators_shift_count_lines() {
+        let (mut vim, mut editor) = setup("a\nb\nc\nd");
+        editor.set_caret(1, 0);
+        press(&mut vim, &mut editor, "2>>");
+        assert_eq!(editor.to_text(), "a\n\tb\n\tc\nd");
+        press(&mut vim, &mut editor, "<<");
+        assert_eq!(editor.to_text(), "a\nb\n\tc\nd");
+    }
+
+    #[test]
+    fn o_opens_a_sibling_and_enters_insert_mode() {
+        let (mut vim, mut editor) = setup("a\n\tb\nc");
+        press(&mut vim, &mut editor, "o");
+        assert_eq!(vim.mode, VimMode::Insert);
+        assert_eq!(editor.to_text(), "a\n\tb\n\nc");
+        assert_eq!(editor.selection.focus.line, 2);
+
+        press(&mut vim, &mut editor, "\x1b");
+        editor.set_caret(1, 0);
+        press(&mut vim, &mut editor, "O");
+        assert_eq!(editor.to_text(), "a\n\t\n\tb\n\nc");
+        assert_eq!(editor.selection.focus.line, 1);
+    }
+
+    #[test]
+    fn insert_mode_leaves_keys_to_the_keymap() {
+        let (mut vim, mut editor) = setup("abc");
+        press(&mut vim, &mut editor, "A");
+        assert_eq!(vim.mode, VimMode::Insert);
+        assert_eq!(editor.selection, SelectionRange::caret(0, 3));
+        assert!(!vim.handle_key(
+            &Key::Character("x".to_string()),
+            Modifiers::empty(),
+            0,
+            &mut editor
+        ));
+        // Escape steps back onto the last character, as in Vim.
+        press(&mut vim, &mut editor, "\x1b");
+        assert_eq!(vim.mode, VimMode::Normal);
+        assert_eq!(editor.selection, SelectionRange::caret(0, 2));
+    }
+
+    #[test]
+    fn za_toggles_the_fold() {
+        let (mut vim, mut editor) = setup("a\n\tb\nc");
+        press(&mut vim, &mut editor, "za");
+        assert!(editor.document.lines[0].collapsed);
+        press(&mut vim, &mut editor, "j");
+        assert_eq!(editor.selection.focus.line, 2);
+        press(&mut vim, &mut editor, "kza");
+        assert!(!editor.document.lines[0].collapsed);
+    }
+
+    #[test]
+    fn x_deletes_characters_under_the_caret() {
+        let (mut vim, mut editor) = setup("abcd");
+        editor.set_caret(0, 1);
+        press(&mut vim, &mut editor, "2x");
+        assert_eq!(editor.to_text(), "ad");
+    }
+
+    #[test]
+    fn undo_and_ctrl_r_redo() {
+        let (mut vim, mut editor) = setup("a\nb\nc");
+        press(&mut vim, &mut editor, "dddd");
+        assert_eq!(editor.to_text(), "c");
+        press(&mut vim, &mut editor, "2u");
+        assert_eq!(editor.to_text(), "a\nb\nc");
+        assert!(vim.handle_key(
+            &Key::Character("r".to_string()),
+            Modifiers::CONTROL,
+            0,
+            &mut editor
+        ));
+        assert_eq!(editor.to_text(), "b\nc");
+    }
+
+    #[test]
+    fn other_control_chords_fall_through() {
+        let (mut vim, mut editor) = setup("a");
+        press(&mut vim, &mut editor, "3");
+        assert!(!vim.handle_key(
+            &Key::Character("z".to_string()),
+            Modifiers::CONTROL,
+            0,
+            &mut editor
+        ));
+        assert_eq!(vim.indicator(), "-- NORMAL --");
+    }
+
+    #[test]
+    fn indicator_shows_pending_input() {
+        let (mut vim, mut editor) = setup("a");
+        assert_eq!(vim.indicator(), "-- NORMAL --");
+        press(&mut vim, &mut editor, "12d");
+        assert_eq!(vim.indicator(), "-- NORMAL -- 12d");
+        press(&mut vim, &mut editor, "\x1b");
+        assert_eq!(vim.indicator(), "-- NORMAL --");
+        press(&mut vim, &mut editor, "i");
+        assert_eq!(vim.indicator(), "-- INSERT --");
+    }
+
+    #[test]
+    fn unknown_keys_are_swallowed_in_normal_mode() {
+        let (mut vim, mut editor) = setup("a");
+        assert!(vim.handle_key(
+            &Key::Character("q".to_string()),
+            Modifiers::empty(),
+            0,
+            &mut editor
+        ));
+        assert_eq!(editor.to_text(), "a");
+        assert!(!vim.handle_key(&Key::ArrowDown, Modifiers::empty(), 0, &mut editor));
+    }
+}